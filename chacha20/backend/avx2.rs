@@ -0,0 +1,112 @@
+//! Eight blocks at a time with AVX2: vector `i` holds word `i` of all eight blocks.
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use super::{xor_in_place, DOUBLE_ROUNDS};
+
+const BLOCKS: usize = 8;
+
+macro_rules! rotl {
+    ($x:expr, $n:literal) => {
+        _mm256_or_si256(_mm256_slli_epi32($x, $n), _mm256_srli_epi32($x, 32 - $n))
+    };
+}
+
+/// Rotations by whole bytes are a single shuffle instead of two shifts and an or.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn rotl_bytes(x: __m256i, shuffle: __m256i) -> __m256i {
+    _mm256_shuffle_epi8(x, shuffle)
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn quarter_round(
+    x: &mut [__m256i; 16],
+    (a, b, c, d): (usize, usize, usize, usize),
+    rot16: __m256i,
+    rot8: __m256i,
+) {
+    x[a] = _mm256_add_epi32(x[a], x[b]);
+    x[d] = rotl_bytes(_mm256_xor_si256(x[d], x[a]), rot16);
+    x[c] = _mm256_add_epi32(x[c], x[d]);
+    x[b] = rotl!(_mm256_xor_si256(x[b], x[c]), 12);
+    x[a] = _mm256_add_epi32(x[a], x[b]);
+    x[d] = rotl_bytes(_mm256_xor_si256(x[d], x[a]), rot8);
+    x[c] = _mm256_add_epi32(x[c], x[d]);
+    x[b] = rotl!(_mm256_xor_si256(x[b], x[c]), 7);
+}
+
+/// Computes eight consecutive keystream blocks starting at the counter in `state`.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn blocks(state: &[u32; 16], out: &mut [u8; BLOCKS * 64]) {
+    let rot16 = _mm256_set_epi8(
+        13, 12, 15, 14, 9, 8, 11, 10, 5, 4, 7, 6, 1, 0, 3, 2,
+        13, 12, 15, 14, 9, 8, 11, 10, 5, 4, 7, 6, 1, 0, 3, 2,
+    );
+    let rot8 = _mm256_set_epi8(
+        14, 13, 12, 15, 10, 9, 8, 11, 6, 5, 4, 7, 2, 1, 0, 3,
+        14, 13, 12, 15, 10, 9, 8, 11, 6, 5, 4, 7, 2, 1, 0, 3,
+    );
+
+    let mut input = [_mm256_setzero_si256(); 16];
+    for i in 0..16 {
+        input[i] = _mm256_set1_epi32(state[i] as i32);
+    }
+    input[12] = _mm256_add_epi32(input[12], _mm256_set_epi32(7, 6, 5, 4, 3, 2, 1, 0));
+
+    let mut x = input;
+    for _ in 0..DOUBLE_ROUNDS {
+        quarter_round(&mut x, (0, 4, 8, 12), rot16, rot8);
+        quarter_round(&mut x, (1, 5, 9, 13), rot16, rot8);
+        quarter_round(&mut x, (2, 6, 10, 14), rot16, rot8);
+        quarter_round(&mut x, (3, 7, 11, 15), rot16, rot8);
+        quarter_round(&mut x, (0, 5, 10, 15), rot16, rot8);
+        quarter_round(&mut x, (1, 6, 11, 12), rot16, rot8);
+        quarter_round(&mut x, (2, 7, 8, 13), rot16, rot8);
+        quarter_round(&mut x, (3, 4, 9, 14), rot16, rot8);
+    }
+    for i in 0..16 {
+        x[i] = _mm256_add_epi32(x[i], input[i]);
+    }
+
+    // The unpack instructions work within each 128-bit half, so after the 4x4
+    // transpose the low half of row `k` belongs to block `k` and the high half
+    // to block `k + 4`
+    for group in 0..4 {
+        let [a, b, c, d] = [x[4 * group], x[4 * group + 1], x[4 * group + 2], x[4 * group + 3]];
+        let ab_lo = _mm256_unpacklo_epi32(a, b);
+        let cd_lo = _mm256_unpacklo_epi32(c, d);
+        let ab_hi = _mm256_unpackhi_epi32(a, b);
+        let cd_hi = _mm256_unpackhi_epi32(c, d);
+
+        let rows = [
+            _mm256_unpacklo_epi64(ab_lo, cd_lo),
+            _mm256_unpackhi_epi64(ab_lo, cd_lo),
+            _mm256_unpacklo_epi64(ab_hi, cd_hi),
+            _mm256_unpackhi_epi64(ab_hi, cd_hi),
+        ];
+        for (block, row) in rows.into_iter().enumerate() {
+            let low = block * 64 + group * 16;
+            let high = (block + 4) * 64 + group * 16;
+            _mm_storeu_si128(out[low..low + 16].as_mut_ptr() as *mut __m128i, _mm256_castsi256_si128(row));
+            _mm_storeu_si128(out[high..high + 16].as_mut_ptr() as *mut __m128i, _mm256_extracti128_si256(row, 1));
+        }
+    }
+}
+
+#[target_feature(enable = "avx2")]
+pub(super) unsafe fn apply_keystream(state: &[u32; 16], data: &mut [u8]) {
+    let mut state = *state;
+    let mut keystream = [0u8; BLOCKS * 64];
+
+    for chunk in data.chunks_mut(BLOCKS * 64) {
+        blocks(&state, &mut keystream);
+        xor_in_place(chunk, &keystream);
+        state[12] = state[12].wrapping_add(BLOCKS as u32);
+    }
+}
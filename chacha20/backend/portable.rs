@@ -0,0 +1,48 @@
+//! One block at a time, in plain Rust.
+
+use super::{xor_in_place, DOUBLE_ROUNDS};
+
+#[inline(always)]
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// Computes one 64-byte keystream block for `state`.
+fn block(state: &[u32; 16], out: &mut [u8; 64]) {
+    let mut x = *state;
+    for _ in 0..DOUBLE_ROUNDS {
+        // Column rounds
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        // Diagonal rounds
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    for i in 0..16 {
+        let word = x[i].wrapping_add(state[i]);
+        out[i * 4..(i + 1) * 4].copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+pub(super) fn apply_keystream(state: &[u32; 16], data: &mut [u8]) {
+    let mut state = *state;
+    let mut keystream = [0u8; 64];
+
+    for chunk in data.chunks_mut(64) {
+        block(&state, &mut keystream);
+        xor_in_place(chunk, &keystream);
+        state[12] = state[12].wrapping_add(1);
+    }
+}
@@ -0,0 +1,88 @@
+//! Four blocks at a time with SSE2: vector `i` holds word `i` of all four blocks.
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use super::{xor_in_place, DOUBLE_ROUNDS};
+
+const BLOCKS: usize = 4;
+
+macro_rules! rotl {
+    ($x:expr, $n:literal) => {
+        _mm_or_si128(_mm_slli_epi32($x, $n), _mm_srli_epi32($x, 32 - $n))
+    };
+}
+
+#[inline]
+#[target_feature(enable = "sse2")]
+unsafe fn quarter_round(x: &mut [__m128i; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = _mm_add_epi32(x[a], x[b]);
+    x[d] = rotl!(_mm_xor_si128(x[d], x[a]), 16);
+    x[c] = _mm_add_epi32(x[c], x[d]);
+    x[b] = rotl!(_mm_xor_si128(x[b], x[c]), 12);
+    x[a] = _mm_add_epi32(x[a], x[b]);
+    x[d] = rotl!(_mm_xor_si128(x[d], x[a]), 8);
+    x[c] = _mm_add_epi32(x[c], x[d]);
+    x[b] = rotl!(_mm_xor_si128(x[b], x[c]), 7);
+}
+
+/// Computes four consecutive keystream blocks starting at the counter in `state`.
+#[inline]
+#[target_feature(enable = "sse2")]
+unsafe fn blocks(state: &[u32; 16], out: &mut [u8; BLOCKS * 64]) {
+    let mut input = [_mm_setzero_si128(); 16];
+    for i in 0..16 {
+        input[i] = _mm_set1_epi32(state[i] as i32);
+    }
+    input[12] = _mm_add_epi32(input[12], _mm_set_epi32(3, 2, 1, 0));
+
+    let mut x = input;
+    for _ in 0..DOUBLE_ROUNDS {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    for i in 0..16 {
+        x[i] = _mm_add_epi32(x[i], input[i]);
+    }
+
+    // Transpose each group of four words so that every vector holds 16
+    // contiguous keystream bytes of a single block
+    for group in 0..4 {
+        let [a, b, c, d] = [x[4 * group], x[4 * group + 1], x[4 * group + 2], x[4 * group + 3]];
+        let ab_lo = _mm_unpacklo_epi32(a, b);
+        let cd_lo = _mm_unpacklo_epi32(c, d);
+        let ab_hi = _mm_unpackhi_epi32(a, b);
+        let cd_hi = _mm_unpackhi_epi32(c, d);
+
+        let rows = [
+            _mm_unpacklo_epi64(ab_lo, cd_lo),
+            _mm_unpackhi_epi64(ab_lo, cd_lo),
+            _mm_unpacklo_epi64(ab_hi, cd_hi),
+            _mm_unpackhi_epi64(ab_hi, cd_hi),
+        ];
+        for (block, row) in rows.into_iter().enumerate() {
+            let offset = block * 64 + group * 16;
+            _mm_storeu_si128(out[offset..offset + 16].as_mut_ptr() as *mut __m128i, row);
+        }
+    }
+}
+
+#[target_feature(enable = "sse2")]
+pub(super) unsafe fn apply_keystream(state: &[u32; 16], data: &mut [u8]) {
+    let mut state = *state;
+    let mut keystream = [0u8; BLOCKS * 64];
+
+    for chunk in data.chunks_mut(BLOCKS * 64) {
+        blocks(&state, &mut keystream);
+        xor_in_place(chunk, &keystream);
+        state[12] = state[12].wrapping_add(BLOCKS as u32);
+    }
+}
@@ -0,0 +1,31 @@
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20_cipher::backend::{self, Backend};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const SIZES: [usize; 3] = [1024, 16 * 1024, 1024 * 1024];
+
+fn bench_backends(c: &mut Criterion) {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 12];
+
+    let mut group = c.benchmark_group("chacha20");
+    for size in SIZES {
+        let mut data = vec![0u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+
+        for backend in Backend::ALL.into_iter().filter(|b| b.is_available()) {
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", backend), size), &size, |b, _| {
+                b.iter(|| backend::apply_keystream_with(backend, &key, &nonce, 0, &mut data))
+            });
+        }
+
+        // The RustCrypto implementation as a baseline
+        group.bench_with_input(BenchmarkId::new("RustCrypto", size), &size, |b, _| {
+            b.iter(|| chacha20::ChaCha20::new(&key.into(), &nonce.into()).apply_keystream(&mut data))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_backends);
+criterion_main!(benches);
@@ -0,0 +1,266 @@
+//! The ChaCha stream cipher parameterized by its round count.
+//!
+//! ChaCha20 is the default everywhere. ChaCha12 and ChaCha8 trade security margin
+//! for speed and are meant for bulk obfuscation and benchmarking only.
+//! ChaCha20 runs on the SIMD backends of [`crate::backend`]; the reduced-round
+//! variants use the `chacha20` crate.
+
+use crate::backend::Keystream;
+use chacha20::cipher::consts::{U12, U32};
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+
+/// A ChaCha round count, selecting the matching cipher implementation.
+pub trait Rounds {
+    /// The underlying stream cipher for this round count.
+    type Cipher: KeyIvInit<KeySize = U32, IvSize = U12> + StreamCipher;
+    /// Number of rounds (each double round counts as two).
+    const COUNT: usize;
+}
+
+/// 8 rounds.
+pub struct R8;
+
+/// 12 rounds.
+pub struct R12;
+
+/// 20 rounds, the standard ChaCha20 of RFC 8439.
+pub struct R20;
+
+impl Rounds for R8 {
+    type Cipher = chacha20::ChaCha8;
+    const COUNT: usize = 8;
+}
+
+impl Rounds for R12 {
+    type Cipher = chacha20::ChaCha12;
+    const COUNT: usize = 12;
+}
+
+impl Rounds for R20 {
+    type Cipher = chacha20::ChaCha20;
+    const COUNT: usize = 20;
+}
+
+/// A ChaCha keystream for one key and nonce, ChaCha20 unless stated otherwise.
+pub struct Cipher<R: Rounds = R20> {
+    inner: Inner<R>,
+}
+
+enum Inner<R: Rounds> {
+    Generic(R::Cipher),
+    Backend(Keystream),
+}
+
+impl<R: Rounds> Cipher<R> {
+    /// Starts the keystream at block 0.
+    pub fn new(key: &[u8; 32], nonce: &[u8; 12]) -> Cipher<R> {
+        let inner = if R::COUNT == R20::COUNT {
+            Inner::Backend(Keystream::new(key, nonce))
+        } else {
+            Inner::Generic(R::Cipher::new(key.into(), nonce.into()))
+        };
+        Cipher { inner }
+    }
+
+    /// XORs the next bytes of the keystream into `data`.
+    pub fn apply_keystream(&mut self, data: &mut [u8]) {
+        match &mut self.inner {
+            Inner::Generic(cipher) => cipher.apply_keystream(data),
+            Inner::Backend(keystream) => keystream.apply_keystream(data),
+        }
+    }
+}
+
+/// Encrypts `data` with the `R`-round ChaCha variant.
+pub fn encrypt<R: Rounds>(data: &[u8], key: &[u8; 32], nonce: &[u8; 12]) -> Vec<u8> {
+    let mut encrypted_data = data.to_vec();
+    Cipher::<R>::new(key, nonce).apply_keystream(&mut encrypted_data);
+    encrypted_data
+}
+
+/// Decrypts `encrypted_data` with the `R`-round ChaCha variant.
+pub fn decrypt<R: Rounds>(encrypted_data: &[u8], key: &[u8; 32], nonce: &[u8; 12]) -> Vec<u8> {
+    // Decryption applies the same keystream again
+    encrypt::<R>(encrypted_data, key, nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_bytes;
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    fn zero_key_keystream<R: Rounds>() -> Vec<u8> {
+        encrypt::<R>(&[0u8; 64], &[0u8; 32], &[0u8; 12])
+    }
+
+    // Zero key, zero nonce vectors from draft-strombergson-chacha-test-vectors (TC1)
+    #[test]
+    fn test_chacha8_known_answer() {
+        let expected = hex_bytes(
+            "3e00ef2f895f40d67f5bb8e81f09a5a12c840ec3ce9a7f3b181be188ef711a1e\
+             984ce172b9216f419f445367456d5619314a42a3da86b001387bfdb80e0cfe42",
+        );
+        assert_eq!(R8::COUNT, 8);
+        assert_eq!(zero_key_keystream::<R8>(), expected);
+    }
+
+    #[test]
+    fn test_chacha12_known_answer() {
+        let expected = hex_bytes(
+            "9bf49a6a0755f953811fce125f2683d50429c3bb49e074147e0089a52eae155f\
+             0564f879d27ae3c02ce82834acfa8c793a629f2ca0de6919610be82f411326be",
+        );
+        assert_eq!(R12::COUNT, 12);
+        assert_eq!(zero_key_keystream::<R12>(), expected);
+    }
+
+    #[test]
+    fn test_chacha20_known_answer() {
+        let expected = hex_bytes(
+            "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7\
+             da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586",
+        );
+        assert_eq!(zero_key_keystream::<R20>(), expected);
+
+        // The default parameter is ChaCha20
+        let mut keystream = [0u8; 64];
+        let mut cipher: Cipher = Cipher::new(&[0u8; 32], &[0u8; 12]);
+        cipher.apply_keystream(&mut keystream);
+        assert_eq!(keystream.to_vec(), expected);
+    }
+
+    // RFC 8439 section 2.4.2, which starts at block counter 1
+    #[test]
+    fn test_chacha20_rfc8439_encryption() {
+        let key: Vec<u8> = (0u8..32).collect();
+        let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+only one tip for the future, sunscreen would be it.";
+        let expected = hex_bytes(
+            "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0b\
+             f91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d8\
+             07ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736\
+             5af90bbf74a35be6b40b8eedf2785e42874d",
+        );
+
+        let mut input = vec![0u8; 64];
+        input.extend_from_slice(plaintext);
+        let ciphertext = encrypt::<R20>(&input, key.as_slice().try_into().unwrap(), &nonce);
+        assert_eq!(ciphertext[64..].to_vec(), expected);
+    }
+
+    #[test]
+    fn test_chacha20_matches_reference_in_pieces() {
+        let mut rng = ChaCha20Rng::from_entropy();
+        let mut key = [0u8; 32];
+        let mut nonce = [0u8; 12];
+        rng.fill_bytes(&mut key);
+        rng.fill_bytes(&mut nonce);
+        let mut data = vec![0u8; 4096];
+        rng.fill_bytes(&mut data);
+
+        let mut expected = data.clone();
+        chacha20::ChaCha20::new((&key).into(), (&nonce).into()).apply_keystream(&mut expected);
+
+        for _ in 0..20 {
+            let mut out = data.clone();
+            let mut cipher = Cipher::<R20>::new(&key, &nonce);
+            let mut start = 0;
+            while start < out.len() {
+                let end = (start + (rng.next_u32() % 300) as usize).min(out.len());
+                cipher.apply_keystream(&mut out[start..end]);
+                start = end;
+            }
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn test_variants_roundtrip_and_differ() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+        let data = b"Test reduced-round variants";
+
+        let chacha8 = encrypt::<R8>(data, &key, &nonce);
+        let chacha12 = encrypt::<R12>(data, &key, &nonce);
+        let chacha20 = encrypt::<R20>(data, &key, &nonce);
+        assert_ne!(chacha8, chacha12);
+        assert_ne!(chacha12, chacha20);
+
+        assert_eq!(decrypt::<R8>(&chacha8, &key, &nonce), data);
+        assert_eq!(decrypt::<R12>(&chacha12, &key, &nonce), data);
+        assert_eq!(decrypt::<R20>(&chacha20, &key, &nonce), data);
+    }
+
+    #[test]
+    fn test_encryption_decryption() {
+        let mut rng = ChaCha20Rng::from_entropy();
+        let mut key = [0u8; 32]; // 256-bit key for ChaCha20
+        let mut nonce = [0u8; 12]; // 96-bit nonce for ChaCha20
+
+        rng.fill_bytes(&mut key); // Fill key with random bytes
+        rng.fill_bytes(&mut nonce); // Fill nonce with random bytes
+
+        let data = b"Test encryption and decryption";
+
+        // Encrypt the data
+        let encrypted_data = encrypt::<R20>(data, &key, &nonce);
+        
+        // Ensure that encrypted data is not equal to original data
+        assert_ne!(encrypted_data, data);
+
+        // Decrypt the data
+        let decrypted_data = decrypt::<R20>(&encrypted_data, &key, &nonce);
+
+        // Ensure that decrypted data matches the original data
+        assert_eq!(decrypted_data, data);
+    }
+
+    #[test]
+    fn test_different_keys() {
+        let mut rng = ChaCha20Rng::from_entropy();
+        let mut key1 = [0u8; 32];
+        let mut key2 = [0u8; 32];
+        let mut nonce = [0u8; 12];
+
+        rng.fill_bytes(&mut key1); // Fill key1 with random bytes
+        rng.fill_bytes(&mut key2); // Fill key2 with random bytes
+        rng.fill_bytes(&mut nonce); // Fill nonce with random bytes
+
+        let data = b"Test different keys";
+
+        // Encrypt the data with the first key
+        let encrypted_data1 = encrypt::<R20>(data, &key1, &nonce);
+        
+        // Encrypt the data with the second key
+        let encrypted_data2 = encrypt::<R20>(data, &key2, &nonce);
+
+        // Ensure that the encrypted outputs are different
+        assert_ne!(encrypted_data1, encrypted_data2);
+    }
+
+    #[test]
+    fn test_same_key_same_nonce() {
+        let mut rng = ChaCha20Rng::from_entropy();
+        let mut key = [0u8; 32];
+        let mut nonce = [0u8; 12];
+
+        rng.fill_bytes(&mut key); // Fill key with random bytes
+        rng.fill_bytes(&mut nonce); // Fill nonce with random bytes
+
+        let data = b"Test same key and nonce";
+
+        // Encrypt the data
+        let encrypted_data = encrypt::<R20>(data, &key, &nonce);
+        
+        // Decrypt the data
+        let decrypted_data1 = decrypt::<R20>(&encrypted_data, &key, &nonce);
+        let decrypted_data2 = decrypt::<R20>(&encrypted_data, &key, &nonce); // Decrypt again
+
+        // Ensure that both decrypted outputs match the original data
+        assert_eq!(decrypted_data1, data);
+        assert_eq!(decrypted_data2, data);
+    }
+}
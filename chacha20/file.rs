@@ -0,0 +1,436 @@
+//! Encrypted file format used by the `chacha20_cipher` binary.
+//!
+//! A file starts with a fixed-size header followed by the body sealed with the
+//! chunked construction from [`crate::stream`]:
+//!
+//! ```text
+//! magic "CC20" | version (1) | key source (1) | m_cost, t_cost, p_cost (3 x u32 LE)
+//!   | salt (16) | nonce prefix (7) | chunk size (u32 LE) | sealed chunks...
+//! ```
+//!
+//! The header is authenticated as associated data of every chunk, so changing
+//! any header field makes decryption fail, and the last-chunk flag detects
+//! truncation of the body.
+
+use crate::secret::SecretKey;
+use crate::stream::{StreamDecryptor, StreamEncryptor, StreamError, NONCE_PREFIX_LEN, TAG_LEN};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// Identifies files written by this tool.
+pub const MAGIC: &[u8; 4] = b"CC20";
+
+/// Current format version.
+pub const VERSION: u8 = 1;
+
+/// Length of the encoded header.
+pub const HEADER_LEN: usize = 4 + 1 + 1 + 12 + SALT_LEN + NONCE_PREFIX_LEN + 4;
+
+/// Plaintext bytes per chunk unless configured otherwise.
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// Largest chunk size accepted in a header; the decryptor allocates one chunk.
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Largest Argon2id costs accepted in a header. The header is only
+/// authenticated after the key is derived, so these bound what a forged file
+/// can make `decrypt` spend.
+pub const MAX_KDF_PARAMS: KdfParams = KdfParams { m_cost: 1024 * 1024, t_cost: 16, p_cost: 16 };
+
+const SALT_LEN: usize = 16;
+const KEY_SOURCE_RAW: u8 = 0;
+const KEY_SOURCE_PASSPHRASE: u8 = 1;
+
+/// Errors returned while encrypting or decrypting a file.
+#[derive(Debug)]
+pub enum FileError {
+    /// Reading the input or writing the output failed.
+    Io(io::Error),
+    /// The input does not start with a valid header.
+    InvalidHeader,
+    /// The file was written by an unknown format version.
+    UnsupportedVersion(u8),
+    /// The file needs a passphrase but a key was supplied, or the other way round.
+    KeySourceMismatch,
+    /// Argon2id rejected the parameters or failed to derive a key.
+    KeyDerivation,
+    /// The body failed authentication or was truncated.
+    Stream(StreamError),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(err) => write!(f, "I/O error: {}", err),
+            FileError::InvalidHeader => write!(f, "not a ChaCha20 encrypted file"),
+            FileError::UnsupportedVersion(version) => write!(f, "unsupported file version {}", version),
+            FileError::KeySourceMismatch => write!(f, "file was encrypted with a different kind of key"),
+            FileError::KeyDerivation => write!(f, "passphrase key derivation failed"),
+            FileError::Stream(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<io::Error> for FileError {
+    fn from(err: io::Error) -> FileError {
+        FileError::Io(err)
+    }
+}
+
+impl From<StreamError> for FileError {
+    fn from(err: StreamError) -> FileError {
+        FileError::Stream(err)
+    }
+}
+
+/// Argon2id cost parameters stored in the header of passphrase-protected files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory in KiB.
+    pub m_cost: u32,
+    /// Number of passes.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> KdfParams {
+        KdfParams {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Whether no cost exceeds the corresponding one in `max`.
+    fn within(&self, max: &KdfParams) -> bool {
+        self.m_cost <= max.m_cost && self.t_cost <= max.t_cost && self.p_cost <= max.p_cost
+    }
+}
+
+fn valid_chunk_size(chunk_size: u32) -> bool {
+    chunk_size > 0 && chunk_size <= MAX_CHUNK_SIZE
+}
+
+/// What the file key comes from.
+pub enum KeyMaterial<'a> {
+    /// A raw 256-bit key, used directly.
+    Key(&'a SecretKey),
+    /// A passphrase stretched with Argon2id and a per-file salt.
+    Passphrase(&'a [u8]),
+}
+
+/// Options for writing an encrypted file.
+#[derive(Debug, Clone, Copy)]
+pub struct EncryptOptions {
+    /// Plaintext bytes per sealed chunk.
+    pub chunk_size: u32,
+    /// Argon2id costs, used only with [`KeyMaterial::Passphrase`].
+    pub kdf: KdfParams,
+}
+
+impl Default for EncryptOptions {
+    fn default() -> EncryptOptions {
+        EncryptOptions {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            kdf: KdfParams::default(),
+        }
+    }
+}
+
+/// The decoded file header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    key_source: u8,
+    kdf: KdfParams,
+    salt: [u8; SALT_LEN],
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    chunk_size: u32,
+}
+
+impl Header {
+    fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut out = [0u8; HEADER_LEN];
+        out[0..4].copy_from_slice(MAGIC);
+        out[4] = VERSION;
+        out[5] = self.key_source;
+        out[6..10].copy_from_slice(&self.kdf.m_cost.to_le_bytes());
+        out[10..14].copy_from_slice(&self.kdf.t_cost.to_le_bytes());
+        out[14..18].copy_from_slice(&self.kdf.p_cost.to_le_bytes());
+        out[18..34].copy_from_slice(&self.salt);
+        out[34..41].copy_from_slice(&self.nonce_prefix);
+        out[41..45].copy_from_slice(&self.chunk_size.to_le_bytes());
+        out
+    }
+
+    fn decode(bytes: &[u8; HEADER_LEN]) -> Result<Header, FileError> {
+        if &bytes[0..4] != MAGIC {
+            return Err(FileError::InvalidHeader);
+        }
+        if bytes[4] != VERSION {
+            return Err(FileError::UnsupportedVersion(bytes[4]));
+        }
+
+        let word = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let header = Header {
+            key_source: bytes[5],
+            kdf: KdfParams {
+                m_cost: word(6),
+                t_cost: word(10),
+                p_cost: word(14),
+            },
+            salt: bytes[18..34].try_into().unwrap(),
+            nonce_prefix: bytes[34..41].try_into().unwrap(),
+            chunk_size: word(41),
+        };
+
+        let known_source = header.key_source == KEY_SOURCE_RAW || header.key_source == KEY_SOURCE_PASSPHRASE;
+        if !known_source || !valid_chunk_size(header.chunk_size) || !header.kdf.within(&MAX_KDF_PARAMS) {
+            return Err(FileError::InvalidHeader);
+        }
+        Ok(header)
+    }
+
+    /// Resolves the file key, checking that the caller supplied the right kind.
+    fn file_key(&self, key: &KeyMaterial) -> Result<SecretKey, FileError> {
+        match (key, self.key_source) {
+            (KeyMaterial::Key(key), KEY_SOURCE_RAW) => Ok((*key).clone()),
+            (KeyMaterial::Passphrase(passphrase), KEY_SOURCE_PASSPHRASE) => {
+                derive_key(passphrase, &self.salt, &self.kdf)
+            }
+            _ => Err(FileError::KeySourceMismatch),
+        }
+    }
+}
+
+/// Stretches `passphrase` into a file key with Argon2id.
+pub fn derive_key(passphrase: &[u8], salt: &[u8], kdf: &KdfParams) -> Result<SecretKey, FileError> {
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32)).map_err(|_| FileError::KeyDerivation)?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+    let mut key = [0u8; 32];
+    argon2
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|_| FileError::KeyDerivation)?;
+    Ok(SecretKey::from_bytes(&mut key))
+}
+
+/// Reads until `buf` is full or the input ends, returning the bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Reads the next chunk into `buf` and reports whether it is the last one.
+fn next_chunk<R: BufRead>(reader: &mut R, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+    let n = read_full(reader, buf)?;
+    let last = n < buf.len() || reader.fill_buf()?.is_empty();
+    Ok((n, last))
+}
+
+/// Encrypts everything from `input` into `output` with default options.
+pub fn encrypt<R: Read, W: Write>(input: R, output: W, key: &KeyMaterial) -> Result<(), FileError> {
+    encrypt_with_options(input, output, key, &EncryptOptions::default())
+}
+
+/// Encrypts everything from `input` into `output`.
+///
+/// As with [`decrypt`], `output` is flushed but not synced.
+pub fn encrypt_with_options<R: Read, W: Write>(
+    input: R,
+    mut output: W,
+    key: &KeyMaterial,
+    options: &EncryptOptions,
+) -> Result<(), FileError> {
+    // Never write a file that decrypt would refuse
+    if !valid_chunk_size(options.chunk_size) || !options.kdf.within(&MAX_KDF_PARAMS) {
+        return Err(FileError::InvalidHeader);
+    }
+
+    let mut header = Header {
+        key_source: KEY_SOURCE_RAW,
+        kdf: KdfParams { m_cost: 0, t_cost: 0, p_cost: 0 },
+        salt: [0u8; SALT_LEN],
+        nonce_prefix: [0u8; NONCE_PREFIX_LEN],
+        chunk_size: options.chunk_size,
+    };
+    OsRng.fill_bytes(&mut header.nonce_prefix);
+    if let KeyMaterial::Passphrase(_) = key {
+        header.key_source = KEY_SOURCE_PASSPHRASE;
+        header.kdf = options.kdf;
+        OsRng.fill_bytes(&mut header.salt);
+    }
+
+    let file_key = header.file_key(key)?;
+    let encoded = header.encode();
+    output.write_all(&encoded)?;
+
+    let mut input = BufReader::new(input);
+    let mut encryptor = StreamEncryptor::new(file_key.as_bytes(), &header.nonce_prefix).with_associated_data(&encoded);
+    let mut buf = vec![0u8; options.chunk_size as usize];
+    loop {
+        let (n, last) = next_chunk(&mut input, &mut buf)?;
+        if last {
+            output.write_all(&encryptor.encrypt_last(&buf[..n])?)?;
+            break;
+        }
+        output.write_all(&encryptor.encrypt_next(&buf[..n])?)?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+/// Decrypts a file produced by [`encrypt`] from `input` into `output`.
+///
+/// `output` is flushed but not synced; callers writing to a file should call
+/// `sync_all` before relying on it.
+///
+/// Each chunk is written only after it verifies, but an error part-way through
+/// leaves the chunks before it in `output`; callers should discard the output
+/// unless this returns `Ok`.
+pub fn decrypt<R: Read, W: Write>(input: R, mut output: W, key: &KeyMaterial) -> Result<(), FileError> {
+    let mut input = BufReader::new(input);
+
+    let mut encoded = [0u8; HEADER_LEN];
+    if read_full(&mut input, &mut encoded)? < HEADER_LEN {
+        return Err(FileError::InvalidHeader);
+    }
+    let header = Header::decode(&encoded)?;
+    let file_key = header.file_key(key)?;
+
+    let mut decryptor = StreamDecryptor::new(file_key.as_bytes(), &header.nonce_prefix).with_associated_data(&encoded);
+    let mut buf = vec![0u8; header.chunk_size as usize + TAG_LEN];
+    loop {
+        let (n, last) = next_chunk(&mut input, &mut buf)?;
+        if last {
+            output.write_all(&decryptor.decrypt_last(&buf[..n])?)?;
+            break;
+        }
+        output.write_all(&decryptor.decrypt_next(&buf[..n])?)?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal Argon2id costs so the tests stay fast
+    const TEST_OPTIONS: EncryptOptions = EncryptOptions {
+        chunk_size: 32,
+        kdf: KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 },
+    };
+
+    fn encrypt_to_vec(data: &[u8], key: &KeyMaterial) -> Vec<u8> {
+        let mut encrypted = Vec::new();
+        encrypt_with_options(data, &mut encrypted, key, &TEST_OPTIONS).unwrap();
+        encrypted
+    }
+
+    fn decrypt_to_vec(encrypted: &[u8], key: &KeyMaterial) -> Result<Vec<u8>, FileError> {
+        let mut decrypted = Vec::new();
+        decrypt(encrypted, &mut decrypted, key)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn test_raw_key_roundtrip() {
+        let key = SecretKey::generate();
+
+        for len in [0usize, 1, 32, 64, 100] {
+            let data: Vec<u8> = (0..len as u8).collect();
+            let encrypted = encrypt_to_vec(&data, &KeyMaterial::Key(&key));
+            assert_eq!(&encrypted[..4], MAGIC);
+            assert_eq!(decrypt_to_vec(&encrypted, &KeyMaterial::Key(&key)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_passphrase_roundtrip_and_wrong_passphrase() {
+        let data = b"Test passphrase-protected file contents";
+        let encrypted = encrypt_to_vec(data, &KeyMaterial::Passphrase(b"correct horse"));
+
+        let decrypted = decrypt_to_vec(&encrypted, &KeyMaterial::Passphrase(b"correct horse")).unwrap();
+        assert_eq!(decrypted, data);
+
+        let wrong = decrypt_to_vec(&encrypted, &KeyMaterial::Passphrase(b"battery staple"));
+        assert!(matches!(wrong, Err(FileError::Stream(StreamError::Authentication))));
+
+        let key = SecretKey::generate();
+        let mismatch = decrypt_to_vec(&encrypted, &KeyMaterial::Key(&key));
+        assert!(matches!(mismatch, Err(FileError::KeySourceMismatch)));
+    }
+
+    #[test]
+    fn test_header_is_authenticated() {
+        let key = SecretKey::generate();
+        let mut encrypted = encrypt_to_vec(b"Test header binding", &KeyMaterial::Key(&key));
+
+        // Flip a salt byte, which is unused for raw keys but still authenticated
+        encrypted[20] ^= 1;
+        let result = decrypt_to_vec(&encrypted, &KeyMaterial::Key(&key));
+        assert!(matches!(result, Err(FileError::Stream(StreamError::Authentication))));
+    }
+
+    #[test]
+    fn test_truncated_and_corrupt_files_are_rejected() {
+        let key = SecretKey::generate();
+        let encrypted = encrypt_to_vec(&[9u8; 100], &KeyMaterial::Key(&key));
+
+        // Cutting the body at a chunk boundary leaves a chunk without the last flag
+        let chunk = TEST_OPTIONS.chunk_size as usize + TAG_LEN;
+        let truncated = &encrypted[..HEADER_LEN + 2 * chunk];
+        assert!(decrypt_to_vec(truncated, &KeyMaterial::Key(&key)).is_err());
+
+        let mut corrupt = encrypted.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(decrypt_to_vec(&corrupt, &KeyMaterial::Key(&key)).is_err());
+
+        assert!(matches!(decrypt_to_vec(b"CC2", &KeyMaterial::Key(&key)), Err(FileError::InvalidHeader)));
+
+        let mut future = encrypted.clone();
+        future[4] = 2;
+        assert!(matches!(decrypt_to_vec(&future, &KeyMaterial::Key(&key)), Err(FileError::UnsupportedVersion(2))));
+    }
+
+    #[test]
+    fn test_oversized_header_fields_are_rejected_before_use() {
+        let encrypted = encrypt_to_vec(b"Test header limits", &KeyMaterial::Passphrase(b"passphrase"));
+        let passphrase = KeyMaterial::Passphrase(b"passphrase");
+
+        // A forged chunk size would otherwise allocate 4 GiB
+        let mut huge_chunks = encrypted.clone();
+        huge_chunks[41..45].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(decrypt_to_vec(&huge_chunks, &passphrase), Err(FileError::InvalidHeader)));
+
+        // Forged Argon2id costs would otherwise run for hours
+        for offset in [6, 10, 14] {
+            let mut expensive = encrypted.clone();
+            expensive[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert!(matches!(decrypt_to_vec(&expensive, &passphrase), Err(FileError::InvalidHeader)));
+        }
+
+        let options = EncryptOptions { chunk_size: MAX_CHUNK_SIZE + 1, ..TEST_OPTIONS };
+        let result = encrypt_with_options(&b""[..], Vec::new(), &passphrase, &options);
+        assert!(matches!(result, Err(FileError::InvalidHeader)));
+    }
+}
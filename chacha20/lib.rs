@@ -0,0 +1,21 @@
+//! ChaCha20 building blocks shared by the `chacha20_cipher` binary.
+
+pub mod backend;
+pub mod cipher;
+pub mod file;
+pub mod mac;
+pub mod noise;
+pub mod nonce;
+pub mod sealing;
+pub mod secret;
+pub mod session;
+pub mod stream;
+
+/// Decodes a hex string in test vectors.
+#[cfg(test)]
+pub(crate) fn hex_bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
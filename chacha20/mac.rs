@@ -0,0 +1,172 @@
+//! Standalone Poly1305 one-time authenticator (RFC 8439 section 2.5).
+//!
+//! A Poly1305 key must authenticate a single message only. Protocols that do not
+//! derive their own one-time keys can use [`poly1305_key_gen`], which takes the
+//! first 32 bytes of ChaCha20 keystream block 0 as in RFC 8439 section 2.6.
+
+use crate::cipher::{Cipher, R20};
+use crate::secret::constant_time_eq;
+use poly1305::universal_hash::{KeyInit, UniversalHash};
+use poly1305::Block;
+use std::fmt;
+
+/// Length of a Poly1305 one-time key.
+pub const KEY_LEN: usize = 32;
+
+/// Length of a Poly1305 tag.
+pub const TAG_LEN: usize = 16;
+
+const BLOCK_LEN: usize = 16;
+
+/// Returned when a tag does not match the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacError;
+
+impl fmt::Display for MacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Poly1305 tag mismatch")
+    }
+}
+
+impl std::error::Error for MacError {}
+
+/// Incremental Poly1305 over messages of any length.
+pub struct Poly1305 {
+    state: poly1305::Poly1305,
+    buffer: [u8; BLOCK_LEN],
+    buffered: usize,
+}
+
+impl Poly1305 {
+    /// Starts a MAC computation under a one-time `key`.
+    pub fn new(key: &[u8; KEY_LEN]) -> Poly1305 {
+        Poly1305 {
+            state: poly1305::Poly1305::new(key.into()),
+            buffer: [0u8; BLOCK_LEN],
+            buffered: 0,
+        }
+    }
+
+    /// Absorbs more message bytes; chunk boundaries do not affect the tag.
+    pub fn update(&mut self, mut data: &[u8]) {
+        // Top up a partially filled block first
+        if self.buffered > 0 {
+            let take = (BLOCK_LEN - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+
+            if self.buffered < BLOCK_LEN {
+                return;
+            }
+            self.state.update(&[Block::from(self.buffer)]);
+            self.buffered = 0;
+        }
+
+        // Full blocks go straight through, the tail waits for more input
+        let mut blocks = data.chunks_exact(BLOCK_LEN);
+        for block in &mut blocks {
+            self.state.update(&[Block::clone_from_slice(block)]);
+        }
+
+        let tail = blocks.remainder();
+        self.buffer[..tail.len()].copy_from_slice(tail);
+        self.buffered = tail.len();
+    }
+
+    /// Returns the tag for everything absorbed so far.
+    pub fn finalize(self) -> [u8; TAG_LEN] {
+        self.state.compute_unpadded(&self.buffer[..self.buffered]).into()
+    }
+
+    /// Checks `tag` against the message in constant time.
+    pub fn verify(self, tag: &[u8]) -> Result<(), MacError> {
+        if constant_time_eq(&self.finalize(), tag) {
+            Ok(())
+        } else {
+            Err(MacError)
+        }
+    }
+}
+
+/// Computes the Poly1305 tag of `data` in one call.
+pub fn compute(key: &[u8; KEY_LEN], data: &[u8]) -> [u8; TAG_LEN] {
+    let mut mac = Poly1305::new(key);
+    mac.update(data);
+    mac.finalize()
+}
+
+/// Derives a one-time Poly1305 key from ChaCha20 keystream block 0 (RFC 8439 section 2.6).
+pub fn poly1305_key_gen(key: &[u8; 32], nonce: &[u8; 12]) -> [u8; KEY_LEN] {
+    let mut one_time_key = [0u8; KEY_LEN];
+    Cipher::<R20>::new(key, nonce).apply_keystream(&mut one_time_key);
+    one_time_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_bytes;
+
+    fn rfc_key() -> [u8; KEY_LEN] {
+        hex_bytes("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b")
+            .try_into()
+            .unwrap()
+    }
+
+    // RFC 8439 section 2.5.2
+    #[test]
+    fn test_rfc8439_tag() {
+        let tag = compute(&rfc_key(), b"Cryptographic Forum Research Group");
+        assert_eq!(tag.to_vec(), hex_bytes("a8061dc1305136c6c22b8baf0c0127a9"));
+    }
+
+    #[test]
+    fn test_incremental_matches_one_shot() {
+        let key = [0x5cu8; KEY_LEN];
+        let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let expected = compute(&key, &data);
+
+        for split in [1usize, 7, 15, 16, 17, 64, 333] {
+            let mut mac = Poly1305::new(&key);
+            for chunk in data.chunks(split) {
+                mac.update(chunk);
+            }
+            assert_eq!(mac.finalize(), expected, "split of {}", split);
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let message = b"Cryptographic Forum Research Group";
+        let tag = compute(&rfc_key(), message);
+
+        let mut mac = Poly1305::new(&rfc_key());
+        mac.update(message);
+        assert_eq!(mac.verify(&tag), Ok(()));
+
+        let mut forged = tag;
+        forged[15] ^= 1;
+        let mut mac = Poly1305::new(&rfc_key());
+        mac.update(message);
+        assert_eq!(mac.verify(&forged), Err(MacError));
+
+        // A truncated tag never verifies
+        let mut mac = Poly1305::new(&rfc_key());
+        mac.update(message);
+        assert_eq!(mac.verify(&tag[..8]), Err(MacError));
+    }
+
+    // RFC 8439 section 2.6.2
+    #[test]
+    fn test_rfc8439_key_generation() {
+        let key: [u8; 32] = (0x80u8..=0x9f).collect::<Vec<u8>>().try_into().unwrap();
+        let nonce = [0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7];
+
+        let one_time_key = poly1305_key_gen(&key, &nonce);
+        assert_eq!(
+            one_time_key.to_vec(),
+            hex_bytes("8ad5a08b905f81cc815040274ab29471a833b637e3fd0da508dbb8e2fdd1a646")
+        );
+    }
+}
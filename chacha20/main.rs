@@ -1,121 +1,192 @@
-use rand::{RngCore, SeedableRng};
-use rand_chacha::ChaCha20Rng;
-use chacha20::{ChaCha20, Key, Nonce, cipher::{KeyIvInit, StreamCipher}};
-
-fn encrypt(data: &[u8], key: &[u8; 32], nonce: &[u8; 12]) -> Vec<u8> {
-    let key = Key::from_slice(key);
-    let nonce = Nonce::from_slice(nonce);
-    let mut cipher = ChaCha20::new(key, nonce);
-    let mut encrypted_data = data.to_vec();
-    cipher.apply_keystream(&mut encrypted_data);
-    encrypted_data
+use chacha20_cipher::file::{self, FileError, KeyMaterial};
+use chacha20_cipher::secret::{SecretKey, KEY_LEN};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use zeroize::Zeroizing;
+
+const USAGE: &str = "\
+Usage:
+  chacha20_cipher encrypt (--key-file PATH | --passphrase-file PATH) [-i INPUT] [-o OUTPUT]
+  chacha20_cipher decrypt (--key-file PATH | --passphrase-file PATH) [-i INPUT] [-o OUTPUT]
+  chacha20_cipher keygen OUTPUT
+
+Input defaults to stdin and output to stdout. A key file holds exactly 32 raw
+bytes; a passphrase file holds the passphrase on its first line.";
+
+enum Command {
+    Encrypt,
+    Decrypt,
 }
 
-fn decrypt(encrypted_data: &[u8], key: &[u8; 32], nonce: &[u8; 12]) -> Vec<u8> {
-    let key = Key::from_slice(key);
-    let nonce = Nonce::from_slice(nonce);
-    let mut cipher = ChaCha20::new(key, nonce);
-    let mut decrypted_data = encrypted_data.to_vec();
-    cipher.apply_keystream(&mut decrypted_data);
-    decrypted_data
+enum KeySource {
+    KeyFile(PathBuf),
+    PassphraseFile(PathBuf),
 }
 
-fn main() {
-    // Generate a random key and nonce
-    let mut rng = ChaCha20Rng::from_entropy();
-    let mut key = [0u8; 32]; // 256-bit key for ChaCha20
-    let mut nonce = [0u8; 12]; // 96-bit nonce for ChaCha20
-
-    rng.fill_bytes(&mut key); // Fill key with random bytes
-    rng.fill_bytes(&mut nonce); // Fill nonce with random bytes
-
-    // Data to encrypt
-    let data = b"Hello, world!";
-    
-    // Encrypt the data
-    let encrypted_data = encrypt(data, &key, &nonce);
-
-    // Output the encrypted data
-    println!("Encrypted data: {:?}", encrypted_data);
-
-    // Decrypt the data
-    let decrypted_data = decrypt(&encrypted_data, &key, &nonce);
-
-    // Output the decrypted data
-    println!("Decrypted data: {:?}", String::from_utf8(decrypted_data).unwrap());
+struct Options {
+    command: Command,
+    key_source: KeySource,
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_encryption_decryption() {
-        let mut rng = ChaCha20Rng::from_entropy();
-        let mut key = [0u8; 32]; // 256-bit key for ChaCha20
-        let mut nonce = [0u8; 12]; // 96-bit nonce for ChaCha20
-
-        rng.fill_bytes(&mut key); // Fill key with random bytes
-        rng.fill_bytes(&mut nonce); // Fill nonce with random bytes
-
-        let data = b"Test encryption and decryption";
-
-        // Encrypt the data
-        let encrypted_data = encrypt(data, &key, &nonce);
-        
-        // Ensure that encrypted data is not equal to original data
-        assert_ne!(encrypted_data, data);
-
-        // Decrypt the data
-        let decrypted_data = decrypt(&encrypted_data, &key, &nonce);
-
-        // Ensure that decrypted data matches the original data
-        assert_eq!(decrypted_data, data);
+fn parse_options(command: Command, args: &[String]) -> Result<Options, String> {
+    let mut key_source = None;
+    let mut input = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().map(PathBuf::from).ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--key-file" | "-k" => key_source = Some(KeySource::KeyFile(value()?)),
+            "--passphrase-file" | "-p" => key_source = Some(KeySource::PassphraseFile(value()?)),
+            "--input" | "-i" => input = Some(value()?),
+            "--output" | "-o" => output = Some(value()?),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
     }
 
-    #[test]
-    fn test_different_keys() {
-        let mut rng = ChaCha20Rng::from_entropy();
-        let mut key1 = [0u8; 32];
-        let mut key2 = [0u8; 32];
-        let mut nonce = [0u8; 12];
+    Ok(Options {
+        command,
+        key_source: key_source.ok_or("either --key-file or --passphrase-file is required")?,
+        input,
+        output,
+    })
+}
 
-        rng.fill_bytes(&mut key1); // Fill key1 with random bytes
-        rng.fill_bytes(&mut key2); // Fill key2 with random bytes
-        rng.fill_bytes(&mut nonce); // Fill nonce with random bytes
+fn read_key_file(path: &Path) -> io::Result<SecretKey> {
+    let contents = Zeroizing::new(fs::read(path)?);
+    let mut bytes: [u8; KEY_LEN] = contents.as_slice().try_into().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("key file must contain exactly {} bytes", KEY_LEN))
+    })?;
+    Ok(SecretKey::from_bytes(&mut bytes))
+}
 
-        let data = b"Test different keys";
+fn read_passphrase_file(path: &Path) -> io::Result<Zeroizing<Vec<u8>>> {
+    let contents = Zeroizing::new(fs::read(path)?);
+    let line = contents.split(|&b| b == b'\n').next().unwrap_or(&[]);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "passphrase file is empty"));
+    }
+    Ok(Zeroizing::new(line.to_vec()))
+}
 
-        // Encrypt the data with the first key
-        let encrypted_data1 = encrypt(data, &key1, &nonce);
-        
-        // Encrypt the data with the second key
-        let encrypted_data2 = encrypt(data, &key2, &nonce);
+fn process<R: Read, W: Write>(command: &Command, input: R, output: W, key: &KeyMaterial) -> Result<(), FileError> {
+    match command {
+        Command::Encrypt => file::encrypt(input, output, key),
+        Command::Decrypt => file::decrypt(input, output, key),
+    }
+}
 
-        // Ensure that the encrypted outputs are different
-        assert_ne!(encrypted_data1, encrypted_data2);
+fn run(options: Options) -> Result<(), String> {
+    let secret_key;
+    let passphrase;
+    let key = match &options.key_source {
+        KeySource::KeyFile(path) => {
+            secret_key = read_key_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            KeyMaterial::Key(&secret_key)
+        }
+        KeySource::PassphraseFile(path) => {
+            passphrase = read_passphrase_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            KeyMaterial::Passphrase(&passphrase)
+        }
+    };
+
+    let input: Box<dyn Read> = match &options.input {
+        Some(path) => Box::new(File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    match &options.output {
+        Some(path) => {
+            // Write next to the destination and only move it into place once the
+            // whole file has been processed, so a failed decryption leaves nothing behind
+            let partial = partial_path(path);
+            let out = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&partial)
+                .map_err(|e| format!("{}: {}", partial.display(), e))?;
+            let mut out = BufWriter::new(out);
+            match process(&options.command, input, &mut out, &key) {
+                Ok(()) => commit_partial(out, &partial, path).map_err(|e| format!("{}: {}", path.display(), e)),
+                Err(err) => {
+                    drop(out);
+                    let _ = fs::remove_file(&partial);
+                    Err(err.to_string())
+                }
+            }
+        }
+        None => process(&options.command, input, BufWriter::new(io::stdout().lock()), &key).map_err(|e| e.to_string()),
     }
+}
 
-    #[test]
-    fn test_same_key_same_nonce() {
-        let mut rng = ChaCha20Rng::from_entropy();
-        let mut key = [0u8; 32];
-        let mut nonce = [0u8; 12];
+/// `report.txt` becomes `report.txt.partial`. The file is created exclusively,
+/// so an existing file of that name is never overwritten or removed.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
 
-        rng.fill_bytes(&mut key); // Fill key with random bytes
-        rng.fill_bytes(&mut nonce); // Fill nonce with random bytes
+/// Syncs the finished partial file to disk, renames it to `path` and syncs the
+/// directory, so a crash never leaves a torn file under the final name.
+fn commit_partial(out: BufWriter<File>, partial: &Path, path: &Path) -> io::Result<()> {
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(partial, path)?;
+
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
 
-        let data = b"Test same key and nonce";
+fn keygen(path: &Path) -> io::Result<()> {
+    let key = SecretKey::generate();
 
-        // Encrypt the data
-        let encrypted_data = encrypt(data, &key, &nonce);
-        
-        // Decrypt the data
-        let decrypted_data1 = decrypt(&encrypted_data, &key, &nonce);
-        let decrypted_data2 = decrypt(&encrypted_data, &key, &nonce); // Decrypt again
+    let mut open = fs::OpenOptions::new();
+    open.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        open.mode(0o600);
+    }
+    open.open(path)?.write_all(key.as_bytes())
+}
 
-        // Ensure that both decrypted outputs match the original data
-        assert_eq!(decrypted_data1, data);
-        assert_eq!(decrypted_data2, data);
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("encrypt") => parse_options(Command::Encrypt, &args[1..]).map(run),
+        Some("decrypt") => parse_options(Command::Decrypt, &args[1..]).map(run),
+        Some("keygen") if args.len() == 2 => {
+            Ok(keygen(Path::new(&args[1])).map_err(|e| format!("{}: {}", args[1], e)))
+        }
+        _ => Err(String::from("missing or unknown command")),
+    };
+
+    match result {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(err)) => {
+            eprintln!("chacha20_cipher: {}", err);
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("chacha20_cipher: {}\n\n{}", err, USAGE);
+            ExitCode::from(2)
+        }
     }
 }
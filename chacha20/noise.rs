@@ -0,0 +1,537 @@
+//! Noise protocol handshake state machine (Noise_NN and Noise_XX over
+//! 25519, ChaChaPoly and SHA256), following revision 34 of the specification.
+//!
+//! This module only produces and consumes handshake messages; framing and the
+//! transport phase live in [`crate::session`].
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroize;
+
+/// Length of a SHA-256 output, the Noise `HASHLEN`.
+const HASH_LEN: usize = 32;
+
+/// Length of an X25519 public key, the Noise `DHLEN`.
+pub const DH_LEN: usize = 32;
+
+/// Length of the ChaChaPoly authentication tag.
+pub const TAG_LEN: usize = 16;
+
+/// Errors produced by the handshake or by a transport cipher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseError {
+    /// A message failed authentication.
+    Decrypt,
+    /// A handshake message is shorter than its pattern requires.
+    ShortMessage,
+    /// The pattern needs a static key that was not provided.
+    MissingStaticKey,
+    /// A message was sent or received out of turn.
+    OutOfTurn,
+    /// The 64-bit nonce counter reached its reserved maximum.
+    NonceExhausted,
+}
+
+impl fmt::Display for NoiseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoiseError::Decrypt => write!(f, "noise message failed authentication"),
+            NoiseError::ShortMessage => write!(f, "noise handshake message is too short"),
+            NoiseError::MissingStaticKey => write!(f, "handshake pattern requires a static key"),
+            NoiseError::OutOfTurn => write!(f, "noise message out of turn"),
+            NoiseError::NonceExhausted => write!(f, "noise nonce counter exhausted"),
+        }
+    }
+}
+
+impl std::error::Error for NoiseError {}
+
+/// Supported handshake patterns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// Anonymous on both sides: `-> e`, `<- e, ee`.
+    NN,
+    /// Static keys exchanged during the handshake:
+    /// `-> e`, `<- e, ee, s, es`, `-> s, se`.
+    XX,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    E,
+    S,
+    EE,
+    ES,
+    SE,
+}
+
+impl Pattern {
+    fn protocol_name(self) -> &'static [u8] {
+        match self {
+            Pattern::NN => b"Noise_NN_25519_ChaChaPoly_SHA256",
+            Pattern::XX => b"Noise_XX_25519_ChaChaPoly_SHA256",
+        }
+    }
+
+    fn messages(self) -> &'static [&'static [Token]] {
+        match self {
+            Pattern::NN => &[&[Token::E], &[Token::E, Token::EE]],
+            Pattern::XX => &[&[Token::E], &[Token::E, Token::EE, Token::S, Token::ES], &[Token::S, Token::SE]],
+        }
+    }
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; HASH_LEN] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// The Noise `HKDF` function producing two outputs.
+fn hkdf(chaining_key: &[u8; HASH_LEN], input_key_material: &[u8]) -> ([u8; HASH_LEN], [u8; HASH_LEN]) {
+    let mut temp_key = hmac_sha256(chaining_key, &[input_key_material]);
+    let output1 = hmac_sha256(&temp_key, &[&[0x01]]);
+    let output2 = hmac_sha256(&temp_key, &[&output1, &[0x02]]);
+    temp_key.zeroize();
+    (output1, output2)
+}
+
+/// A ChaChaPoly key with its 64-bit message counter (Noise `CipherState`).
+pub struct CipherState {
+    key: Option<[u8; 32]>,
+    nonce: u64,
+}
+
+impl CipherState {
+    fn empty() -> CipherState {
+        CipherState { key: None, nonce: 0 }
+    }
+
+    fn with_key(key: [u8; 32]) -> CipherState {
+        CipherState { key: Some(key), nonce: 0 }
+    }
+
+    /// Noise nonces are four zero bytes followed by the little-endian counter.
+    fn nonce_bytes(nonce: u64) -> Nonce {
+        let mut bytes = [0u8; 12];
+        bytes[4..].copy_from_slice(&nonce.to_le_bytes());
+        Nonce::from(bytes)
+    }
+
+    /// Number of messages processed with the current key.
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Encrypts `plaintext`, or passes it through before a key is established.
+    pub fn encrypt_with_ad(&mut self, ad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return Ok(plaintext.to_vec()),
+        };
+        if self.nonce == u64::MAX {
+            return Err(NoiseError::NonceExhausted);
+        }
+
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+            .encrypt(&Self::nonce_bytes(self.nonce), Payload { msg: plaintext, aad: ad })
+            .map_err(|_| NoiseError::Decrypt)?;
+        self.nonce += 1;
+        Ok(ciphertext)
+    }
+
+    /// Decrypts `ciphertext`, or passes it through before a key is established.
+    pub fn decrypt_with_ad(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return Ok(ciphertext.to_vec()),
+        };
+        if self.nonce == u64::MAX {
+            return Err(NoiseError::NonceExhausted);
+        }
+
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(&Self::nonce_bytes(self.nonce), Payload { msg: ciphertext, aad: ad })
+            .map_err(|_| NoiseError::Decrypt)?;
+        self.nonce += 1;
+        Ok(plaintext)
+    }
+
+    /// Replaces the key with one derived from it (Noise `Rekey()`); the counter is kept.
+    pub fn rekey(&mut self) {
+        if let Some(key) = &mut self.key {
+            let mut new_key = ChaCha20Poly1305::new(Key::from_slice(key))
+                .encrypt(&Self::nonce_bytes(u64::MAX), Payload { msg: &[0u8; 32], aad: &[] })
+                .expect("rekey input is short");
+            key.copy_from_slice(&new_key[..32]);
+            new_key.zeroize();
+        }
+    }
+}
+
+impl Drop for CipherState {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl fmt::Debug for CipherState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CipherState").field("key", &"[REDACTED]").field("nonce", &self.nonce).finish()
+    }
+}
+
+/// Chaining key and handshake hash (Noise `SymmetricState`).
+struct SymmetricState {
+    chaining_key: [u8; HASH_LEN],
+    hash: [u8; HASH_LEN],
+    cipher: CipherState,
+}
+
+impl SymmetricState {
+    fn new(protocol_name: &[u8]) -> SymmetricState {
+        let mut hash = [0u8; HASH_LEN];
+        if protocol_name.len() <= HASH_LEN {
+            hash[..protocol_name.len()].copy_from_slice(protocol_name);
+        } else {
+            hash = Sha256::digest(protocol_name).into();
+        }
+        SymmetricState { chaining_key: hash, hash, cipher: CipherState::empty() }
+    }
+
+    fn mix_key(&mut self, input_key_material: &[u8]) {
+        let (chaining_key, temp_key) = hkdf(&self.chaining_key, input_key_material);
+        self.chaining_key = chaining_key;
+        self.cipher = CipherState::with_key(temp_key);
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let mut hasher = Sha256::new();
+        hasher.update(self.hash);
+        hasher.update(data);
+        self.hash = hasher.finalize().into();
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let ciphertext = self.cipher.encrypt_with_ad(&self.hash, plaintext)?;
+        self.mix_hash(&ciphertext);
+        Ok(ciphertext)
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let plaintext = self.cipher.decrypt_with_ad(&self.hash, ciphertext)?;
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    fn split(&self) -> (CipherState, CipherState) {
+        let (first, second) = hkdf(&self.chaining_key, &[]);
+        (CipherState::with_key(first), CipherState::with_key(second))
+    }
+}
+
+impl Drop for SymmetricState {
+    fn drop(&mut self) {
+        self.chaining_key.zeroize();
+    }
+}
+
+/// The two transport ciphers produced by a completed handshake.
+pub struct TransportKeys {
+    /// Encrypts messages this side sends.
+    pub send: CipherState,
+    /// Decrypts messages this side receives.
+    pub receive: CipherState,
+    /// The peer's static public key, when the pattern transmits one.
+    pub remote_static: Option<[u8; DH_LEN]>,
+    /// The final handshake hash, usable for channel binding.
+    pub handshake_hash: [u8; HASH_LEN],
+}
+
+/// One side of a handshake in progress (Noise `HandshakeState`).
+pub struct HandshakeState {
+    pattern: Pattern,
+    initiator: bool,
+    symmetric: SymmetricState,
+    local_static: Option<StaticSecret>,
+    local_ephemeral: Option<StaticSecret>,
+    remote_static: Option<PublicKey>,
+    remote_ephemeral: Option<PublicKey>,
+    message_index: usize,
+}
+
+impl HandshakeState {
+    /// Starts a handshake; `local_static` is required by patterns that send `s`.
+    pub fn new(
+        pattern: Pattern,
+        initiator: bool,
+        prologue: &[u8],
+        local_static: Option<StaticSecret>,
+    ) -> Result<HandshakeState, NoiseError> {
+        let sends_static = pattern
+            .messages()
+            .iter()
+            .enumerate()
+            .any(|(i, tokens)| i.is_multiple_of(2) == initiator && tokens.contains(&Token::S));
+        if sends_static && local_static.is_none() {
+            return Err(NoiseError::MissingStaticKey);
+        }
+
+        let mut symmetric = SymmetricState::new(pattern.protocol_name());
+        symmetric.mix_hash(prologue);
+
+        Ok(HandshakeState {
+            pattern,
+            initiator,
+            symmetric,
+            local_static,
+            local_ephemeral: None,
+            remote_static: None,
+            remote_ephemeral: None,
+            message_index: 0,
+        })
+    }
+
+    /// Whether every handshake message has been processed.
+    pub fn is_finished(&self) -> bool {
+        self.message_index == self.pattern.messages().len()
+    }
+
+    /// Whether the next handshake message is ours to write.
+    pub fn is_my_turn(&self) -> bool {
+        !self.is_finished() && self.message_index.is_multiple_of(2) == self.initiator
+    }
+
+    fn dh(&self, token: Token) -> Result<[u8; DH_LEN], NoiseError> {
+        // Token names list the initiator's key first, so map them onto our side
+        let (local, remote) = match (token, self.initiator) {
+            (Token::EE, _) => (&self.local_ephemeral, &self.remote_ephemeral),
+            (Token::ES, true) | (Token::SE, false) => (&self.local_ephemeral, &self.remote_static),
+            (Token::ES, false) | (Token::SE, true) => (&self.local_static, &self.remote_ephemeral),
+            (Token::E | Token::S, _) => unreachable!("not a DH token"),
+        };
+
+        match (local, remote) {
+            (Some(local), Some(remote)) => Ok(local.diffie_hellman(remote).to_bytes()),
+            _ => Err(NoiseError::MissingStaticKey),
+        }
+    }
+
+    /// Writes the next handshake message carrying `payload`.
+    pub fn write_message(&mut self, payload: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        if !self.is_my_turn() {
+            return Err(NoiseError::OutOfTurn);
+        }
+
+        let mut message = Vec::new();
+        for &token in self.pattern.messages()[self.message_index] {
+            match token {
+                Token::E => {
+                    let ephemeral = self
+                        .local_ephemeral
+                        .get_or_insert_with(|| StaticSecret::random_from_rng(OsRng));
+                    let public = PublicKey::from(&*ephemeral);
+                    message.extend_from_slice(public.as_bytes());
+                    self.symmetric.mix_hash(public.as_bytes());
+                }
+                Token::S => {
+                    let local_static = self.local_static.as_ref().ok_or(NoiseError::MissingStaticKey)?;
+                    let public = PublicKey::from(local_static);
+                    let encrypted = self.symmetric.encrypt_and_hash(public.as_bytes())?;
+                    message.extend_from_slice(&encrypted);
+                }
+                Token::EE | Token::ES | Token::SE => {
+                    let shared = self.dh(token)?;
+                    self.symmetric.mix_key(&shared);
+                }
+            }
+        }
+
+        message.extend_from_slice(&self.symmetric.encrypt_and_hash(payload)?);
+        self.message_index += 1;
+        Ok(message)
+    }
+
+    /// Reads the next handshake message and returns its payload.
+    pub fn read_message(&mut self, message: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        if self.is_finished() || self.is_my_turn() {
+            return Err(NoiseError::OutOfTurn);
+        }
+
+        let mut rest = message;
+        for &token in self.pattern.messages()[self.message_index] {
+            match token {
+                Token::E => {
+                    if rest.len() < DH_LEN {
+                        return Err(NoiseError::ShortMessage);
+                    }
+                    let (public, tail) = rest.split_at(DH_LEN);
+                    let public: [u8; DH_LEN] = public.try_into().unwrap();
+                    self.remote_ephemeral = Some(PublicKey::from(public));
+                    self.symmetric.mix_hash(&public);
+                    rest = tail;
+                }
+                Token::S => {
+                    // Encrypted once a key is established, which is always the case for XX
+                    let len = DH_LEN + if self.symmetric.cipher.key.is_some() { TAG_LEN } else { 0 };
+                    if rest.len() < len {
+                        return Err(NoiseError::ShortMessage);
+                    }
+                    let (encrypted, tail) = rest.split_at(len);
+                    let public: [u8; DH_LEN] = self.symmetric.decrypt_and_hash(encrypted)?.try_into().unwrap();
+                    self.remote_static = Some(PublicKey::from(public));
+                    rest = tail;
+                }
+                Token::EE | Token::ES | Token::SE => {
+                    let shared = self.dh(token)?;
+                    self.symmetric.mix_key(&shared);
+                }
+            }
+        }
+
+        let payload = self.symmetric.decrypt_and_hash(rest)?;
+        self.message_index += 1;
+        Ok(payload)
+    }
+
+    /// Derives the transport ciphers once the handshake is finished.
+    pub fn into_transport(self) -> Result<TransportKeys, NoiseError> {
+        if !self.is_finished() {
+            return Err(NoiseError::OutOfTurn);
+        }
+
+        let (initiator_to_responder, responder_to_initiator) = self.symmetric.split();
+        let (send, receive) = if self.initiator {
+            (initiator_to_responder, responder_to_initiator)
+        } else {
+            (responder_to_initiator, initiator_to_responder)
+        };
+
+        Ok(TransportKeys {
+            send,
+            receive,
+            remote_static: self.remote_static.map(|key| key.to_bytes()),
+            handshake_hash: self.symmetric.hash,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(pattern: Pattern, initiator_static: Option<StaticSecret>, responder_static: Option<StaticSecret>) -> (TransportKeys, TransportKeys) {
+        let mut initiator = HandshakeState::new(pattern, true, b"prologue", initiator_static).unwrap();
+        let mut responder = HandshakeState::new(pattern, false, b"prologue", responder_static).unwrap();
+
+        while !initiator.is_finished() {
+            let (writer, reader) = if initiator.is_my_turn() {
+                (&mut initiator, &mut responder)
+            } else {
+                (&mut responder, &mut initiator)
+            };
+            let message = writer.write_message(b"").unwrap();
+            reader.read_message(&message).unwrap();
+        }
+
+        (initiator.into_transport().unwrap(), responder.into_transport().unwrap())
+    }
+
+    #[test]
+    fn test_nn_handshake_agrees_on_keys() {
+        let (mut initiator, mut responder) = handshake(Pattern::NN, None, None);
+        assert_eq!(initiator.handshake_hash, responder.handshake_hash);
+
+        let ciphertext = initiator.send.encrypt_with_ad(&[], b"ping").unwrap();
+        assert_eq!(responder.receive.decrypt_with_ad(&[], &ciphertext).unwrap(), b"ping");
+
+        let ciphertext = responder.send.encrypt_with_ad(&[], b"pong").unwrap();
+        assert_eq!(initiator.receive.decrypt_with_ad(&[], &ciphertext).unwrap(), b"pong");
+    }
+
+    #[test]
+    fn test_xx_handshake_exchanges_static_keys() {
+        let initiator_static = StaticSecret::random_from_rng(OsRng);
+        let responder_static = StaticSecret::random_from_rng(OsRng);
+        let initiator_public = PublicKey::from(&initiator_static).to_bytes();
+        let responder_public = PublicKey::from(&responder_static).to_bytes();
+
+        let (initiator, responder) = handshake(Pattern::XX, Some(initiator_static), Some(responder_static));
+        assert_eq!(initiator.remote_static, Some(responder_public));
+        assert_eq!(responder.remote_static, Some(initiator_public));
+    }
+
+    #[test]
+    fn test_xx_requires_static_key() {
+        let result = HandshakeState::new(Pattern::XX, true, b"", None);
+        assert!(matches!(result, Err(NoiseError::MissingStaticKey)));
+    }
+
+    #[test]
+    fn test_tampered_handshake_message_is_rejected() {
+        let mut initiator = HandshakeState::new(Pattern::NN, true, b"", None).unwrap();
+        let mut responder = HandshakeState::new(Pattern::NN, false, b"", None).unwrap();
+
+        let first = initiator.write_message(b"").unwrap();
+        responder.read_message(&first).unwrap();
+        let mut second = responder.write_message(b"hello").unwrap();
+        *second.last_mut().unwrap() ^= 1;
+        assert_eq!(initiator.read_message(&second), Err(NoiseError::Decrypt));
+    }
+
+    #[test]
+    fn test_mismatched_prologue_fails() {
+        let mut initiator = HandshakeState::new(Pattern::NN, true, b"v1", None).unwrap();
+        let mut responder = HandshakeState::new(Pattern::NN, false, b"v2", None).unwrap();
+
+        let first = initiator.write_message(b"").unwrap();
+        responder.read_message(&first).unwrap();
+        let second = responder.write_message(b"").unwrap();
+        assert_eq!(initiator.read_message(&second), Err(NoiseError::Decrypt));
+    }
+
+    #[test]
+    fn test_interoperates_with_snow() {
+        let mut ours = HandshakeState::new(Pattern::XX, true, b"", Some(StaticSecret::random_from_rng(OsRng))).unwrap();
+        let builder = snow::Builder::new("Noise_XX_25519_ChaChaPoly_SHA256".parse().unwrap());
+        let keypair = builder.generate_keypair().unwrap();
+        let mut theirs = builder.local_private_key(&keypair.private).build_responder().unwrap();
+        let mut buf = [0u8; 1024];
+
+        let message = ours.write_message(b"one").unwrap();
+        let n = theirs.read_message(&message, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"one");
+
+        let n = theirs.write_message(b"two", &mut buf).unwrap();
+        assert_eq!(ours.read_message(&buf[..n]).unwrap(), b"two");
+
+        let message = ours.write_message(b"three").unwrap();
+        let n = theirs.read_message(&message, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"three");
+
+        let mut keys = ours.into_transport().unwrap();
+        let mut theirs = theirs.into_transport_mode().unwrap();
+        assert_eq!(keys.remote_static.unwrap().to_vec(), keypair.public);
+
+        let ciphertext = keys.send.encrypt_with_ad(&[], b"transport").unwrap();
+        let n = theirs.read_message(&ciphertext, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"transport");
+
+        let n = theirs.write_message(b"reply", &mut buf).unwrap();
+        assert_eq!(keys.receive.decrypt_with_ad(&[], &buf[..n]).unwrap(), b"reply");
+
+        // Both implementations must derive the same key on rekey
+        keys.send.rekey();
+        theirs.rekey_incoming();
+        let ciphertext = keys.send.encrypt_with_ad(&[], b"after rekey").unwrap();
+        let n = theirs.read_message(&ciphertext, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"after rekey");
+    }
+}
@@ -0,0 +1,289 @@
+//! Nonce sequences that hand out 96-bit ChaCha20 nonces without caller involvement.
+//!
+//! A [`NonceSequence`] is consumed by [`crate::sealing::SealingKey`], so the only way
+//! to encrypt with a sealing key is through a sequence that never repeats itself.
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Length of a ChaCha20 (IETF) nonce.
+pub const NONCE_LEN: usize = 12;
+
+/// Random nonces allowed per key before the collision probability exceeds 2^-32.
+pub const RANDOM_NONCE_LIMIT: u64 = 1 << 32;
+
+/// Errors returned when a sequence cannot produce another nonce.
+#[derive(Debug)]
+pub enum NonceError {
+    /// Every nonce the sequence may safely hand out has been used.
+    Exhausted,
+    /// The persisted counter could not be read or written.
+    Io(io::Error),
+    /// The persisted counter file does not contain a valid counter.
+    Corrupt,
+}
+
+impl fmt::Display for NonceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NonceError::Exhausted => write!(f, "nonce sequence exhausted"),
+            NonceError::Io(err) => write!(f, "nonce counter I/O error: {}", err),
+            NonceError::Corrupt => write!(f, "nonce counter file is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for NonceError {}
+
+impl From<io::Error> for NonceError {
+    fn from(err: io::Error) -> NonceError {
+        NonceError::Io(err)
+    }
+}
+
+/// A source of nonces that never returns the same value twice for one key.
+pub trait NonceSequence {
+    /// Returns the next nonce, or an error once the sequence cannot continue safely.
+    fn advance(&mut self) -> Result<[u8; NONCE_LEN], NonceError>;
+}
+
+/// Builds `prefix || counter` with the counter in big-endian order.
+fn counter_nonce(prefix: &[u8; 4], counter: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..4].copy_from_slice(prefix);
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+/// Nonces formed from a fixed 32-bit prefix and a 64-bit message counter.
+pub struct CounterNonceSequence {
+    prefix: [u8; 4],
+    counter: u64,
+}
+
+impl CounterNonceSequence {
+    /// Starts counting from zero under `prefix`.
+    pub fn new(prefix: [u8; 4]) -> CounterNonceSequence {
+        CounterNonceSequence { prefix, counter: 0 }
+    }
+}
+
+impl NonceSequence for CounterNonceSequence {
+    fn advance(&mut self) -> Result<[u8; NONCE_LEN], NonceError> {
+        let nonce = counter_nonce(&self.prefix, self.counter);
+        self.counter = self.counter.checked_add(1).ok_or(NonceError::Exhausted)?;
+        Ok(nonce)
+    }
+}
+
+/// Uniformly random nonces, capped so that a birthday collision stays negligible.
+pub struct RandomNonceSequence {
+    issued: u64,
+    limit: u64,
+}
+
+impl RandomNonceSequence {
+    /// Allows up to [`RANDOM_NONCE_LIMIT`] nonces.
+    pub fn new() -> RandomNonceSequence {
+        RandomNonceSequence::with_limit(RANDOM_NONCE_LIMIT)
+    }
+
+    /// Allows up to `limit` nonces; limits above [`RANDOM_NONCE_LIMIT`] are clamped.
+    pub fn with_limit(limit: u64) -> RandomNonceSequence {
+        RandomNonceSequence { issued: 0, limit: limit.min(RANDOM_NONCE_LIMIT) }
+    }
+}
+
+impl Default for RandomNonceSequence {
+    fn default() -> RandomNonceSequence {
+        RandomNonceSequence::new()
+    }
+}
+
+impl NonceSequence for RandomNonceSequence {
+    fn advance(&mut self) -> Result<[u8; NONCE_LEN], NonceError> {
+        if self.issued >= self.limit {
+            return Err(NonceError::Exhausted);
+        }
+        self.issued += 1;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        Ok(nonce)
+    }
+}
+
+/// A counter sequence whose position survives process restarts.
+///
+/// The file records the first counter value that has not been reserved yet.
+/// Counters are reserved in batches and the file is updated before any nonce
+/// from a new batch is returned, so a crash can skip nonces but never repeat one.
+pub struct PersistedCounterNonceSequence {
+    path: PathBuf,
+    prefix: [u8; 4],
+    counter: u64,
+    reserved_until: u64,
+    batch: u64,
+}
+
+impl PersistedCounterNonceSequence {
+    /// Default number of counters reserved per write to the counter file.
+    pub const DEFAULT_BATCH: u64 = 1024;
+
+    /// Opens (or creates) the counter file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P, prefix: [u8; 4]) -> Result<PersistedCounterNonceSequence, NonceError> {
+        PersistedCounterNonceSequence::with_batch(path, prefix, Self::DEFAULT_BATCH)
+    }
+
+    /// Opens the counter file, reserving `batch` counters per write.
+    pub fn with_batch<P: AsRef<Path>>(
+        path: P,
+        prefix: [u8; 4],
+        batch: u64,
+    ) -> Result<PersistedCounterNonceSequence, NonceError> {
+        let path = path.as_ref().to_path_buf();
+        let counter = match fs::read_to_string(&path) {
+            Ok(contents) => contents.trim().parse::<u64>().map_err(|_| NonceError::Corrupt)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(PersistedCounterNonceSequence {
+            path,
+            prefix,
+            counter,
+            reserved_until: counter,
+            batch: batch.max(1),
+        })
+    }
+
+    /// Atomically and durably records `value` as the next unreserved counter.
+    fn persist(&self, value: u64) -> Result<(), NonceError> {
+        // `counter` becomes `counter.tmp`, never clobbering a sibling file
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut file = File::create(&tmp)?;
+        file.write_all(value.to_string().as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp, &self.path)?;
+
+        // The rename itself is only durable once the directory is synced
+        #[cfg(unix)]
+        {
+            let dir = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl NonceSequence for PersistedCounterNonceSequence {
+    fn advance(&mut self) -> Result<[u8; NONCE_LEN], NonceError> {
+        if self.counter == u64::MAX {
+            return Err(NonceError::Exhausted);
+        }
+
+        if self.counter >= self.reserved_until {
+            let reserved_until = self.counter.saturating_add(self.batch);
+            self.persist(reserved_until)?;
+            self.reserved_until = reserved_until;
+        }
+
+        let nonce = counter_nonce(&self.prefix, self.counter);
+        self.counter += 1;
+        Ok(nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn temp_counter_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}-{}", name, std::process::id(), OsRng.next_u64()))
+    }
+
+    #[test]
+    fn test_counter_sequence_is_unique_and_ordered() {
+        let mut nonces = CounterNonceSequence::new([1, 2, 3, 4]);
+
+        let first = nonces.advance().unwrap();
+        let second = nonces.advance().unwrap();
+        assert_eq!(first, [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(second, [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        nonces.counter = u64::MAX;
+        assert!(matches!(nonces.advance(), Err(NonceError::Exhausted)));
+    }
+
+    #[test]
+    fn test_random_sequence_respects_limit() {
+        let mut nonces = RandomNonceSequence::with_limit(100);
+
+        let issued: HashSet<_> = (0..100).map(|_| nonces.advance().unwrap()).collect();
+        assert_eq!(issued.len(), 100);
+        assert!(matches!(nonces.advance(), Err(NonceError::Exhausted)));
+    }
+
+    #[test]
+    fn test_persisted_sequence_never_repeats_across_restarts() {
+        let path = temp_counter_path("chacha20-nonce-counter");
+        let mut seen = HashSet::new();
+
+        // First run uses a few nonces and then "crashes" mid-batch
+        let mut nonces = PersistedCounterNonceSequence::with_batch(&path, [9; 4], 4).unwrap();
+        for _ in 0..6 {
+            assert!(seen.insert(nonces.advance().unwrap()));
+        }
+        drop(nonces);
+
+        // The restart resumes after the reserved batch instead of reusing counters
+        let mut nonces = PersistedCounterNonceSequence::with_batch(&path, [9; 4], 4).unwrap();
+        let resumed = nonces.advance().unwrap();
+        assert!(seen.insert(resumed));
+        assert_eq!(resumed, counter_nonce(&[9; 4], 8));
+
+        // The temporary file sits next to the counter under its full name
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        assert!(!Path::new(&tmp).exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_persisting_leaves_sibling_files_alone() {
+        let path = temp_counter_path("chacha20-nonce-sibling").with_extension("counter");
+        let sibling = path.with_extension("tmp");
+        fs::write(&sibling, "unrelated").unwrap();
+
+        let mut nonces = PersistedCounterNonceSequence::open(&path, [0; 4]).unwrap();
+        nonces.advance().unwrap();
+        assert_eq!(fs::read_to_string(&sibling).unwrap(), "unrelated");
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&sibling).unwrap();
+    }
+
+    #[test]
+    fn test_persisted_sequence_rejects_corrupt_file() {
+        let path = temp_counter_path("chacha20-nonce-corrupt");
+        fs::write(&path, "not a counter").unwrap();
+
+        let result = PersistedCounterNonceSequence::open(&path, [0; 4]);
+        assert!(matches!(result, Err(NonceError::Corrupt)));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! ChaCha20-Poly1305 keys whose nonces come from a [`NonceSequence`].
+//!
+//! Sealed messages are laid out as `nonce || ciphertext || tag`, so the opening
+//! side never needs to be told the nonce separately.
+
+use crate::nonce::{NonceError, NonceSequence, NONCE_LEN};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::fmt;
+
+/// Length of the Poly1305 tag appended to every sealed message.
+pub const TAG_LEN: usize = 16;
+
+/// Errors returned while sealing or opening a message.
+#[derive(Debug)]
+pub enum SealError {
+    /// The nonce sequence could not produce another nonce.
+    Nonce(NonceError),
+    /// The plaintext exceeds the ChaCha20-Poly1305 length limit.
+    TooLong,
+    /// The message is shorter than a nonce plus a tag.
+    TooShort,
+    /// The message failed authentication.
+    Authentication,
+}
+
+impl fmt::Display for SealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SealError::Nonce(err) => write!(f, "{}", err),
+            SealError::TooLong => write!(f, "plaintext is too long to seal"),
+            SealError::TooShort => write!(f, "sealed message is too short"),
+            SealError::Authentication => write!(f, "sealed message failed authentication"),
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
+
+impl From<NonceError> for SealError {
+    fn from(err: NonceError) -> SealError {
+        SealError::Nonce(err)
+    }
+}
+
+/// An encryption key bound to the nonce sequence it draws from.
+pub struct SealingKey<N: NonceSequence> {
+    aead: ChaCha20Poly1305,
+    nonces: N,
+}
+
+impl<N: NonceSequence> SealingKey<N> {
+    /// Binds `key` to `nonces`; the sequence is the only source of nonces for this key.
+    pub fn new(key: &[u8; 32], nonces: N) -> SealingKey<N> {
+        SealingKey {
+            aead: ChaCha20Poly1305::new(Key::from_slice(key)),
+            nonces,
+        }
+    }
+
+    /// Encrypts and authenticates `plaintext` and `aad` under the next nonce.
+    pub fn seal(&mut self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, SealError> {
+        let nonce = self.nonces.advance()?;
+        let ciphertext = self
+            .aead
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+            .map_err(|_| SealError::TooLong)?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+}
+
+/// The decryption counterpart of [`SealingKey`].
+pub struct OpeningKey {
+    aead: ChaCha20Poly1305,
+}
+
+impl OpeningKey {
+    /// Creates an opening key for messages sealed under `key`.
+    pub fn new(key: &[u8; 32]) -> OpeningKey {
+        OpeningKey {
+            aead: ChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    /// Verifies and decrypts a message produced by [`SealingKey::seal`].
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, SealError> {
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(SealError::TooShort);
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| SealError::Authentication)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nonce::{CounterNonceSequence, RandomNonceSequence};
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let key = [0x42u8; 32];
+        let mut sealing_key = SealingKey::new(&key, CounterNonceSequence::new([0; 4]));
+        let opening_key = OpeningKey::new(&key);
+
+        let sealed = sealing_key.seal(b"aad", b"Test sealing").unwrap();
+        assert_eq!(sealed.len(), NONCE_LEN + b"Test sealing".len() + TAG_LEN);
+        assert_eq!(opening_key.open(b"aad", &sealed).unwrap(), b"Test sealing");
+        assert!(matches!(opening_key.open(b"other", &sealed), Err(SealError::Authentication)));
+    }
+
+    #[test]
+    fn test_repeated_seals_use_fresh_nonces() {
+        let key = [7u8; 32];
+        let mut sealing_key = SealingKey::new(&key, RandomNonceSequence::new());
+
+        let first = sealing_key.seal(b"", b"same message").unwrap();
+        let second = sealing_key.seal(b"", b"same message").unwrap();
+        assert_ne!(first[..NONCE_LEN], second[..NONCE_LEN]);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_exhausted_sequence_refuses_to_seal() {
+        let key = [1u8; 32];
+        let mut sealing_key = SealingKey::new(&key, RandomNonceSequence::with_limit(1));
+
+        assert!(sealing_key.seal(b"", b"one").is_ok());
+        assert!(matches!(sealing_key.seal(b"", b"two"), Err(SealError::Nonce(NonceError::Exhausted))));
+    }
+
+    #[test]
+    fn test_open_rejects_short_input() {
+        let opening_key = OpeningKey::new(&[0u8; 32]);
+        assert!(matches!(opening_key.open(b"", &[0u8; NONCE_LEN + TAG_LEN - 1]), Err(SealError::TooShort)));
+    }
+}
@@ -0,0 +1,178 @@
+//! Secret key handling: zeroize-on-drop storage, redacted `Debug` and
+//! constant-time comparison.
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fmt;
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Length of a ChaCha20 key.
+pub const KEY_LEN: usize = 32;
+
+/// A 256-bit key that is wiped from memory when dropped.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct SecretKey([u8; KEY_LEN]);
+
+impl SecretKey {
+    /// Takes ownership of `bytes`, wiping the caller's copy.
+    pub fn from_bytes(bytes: &mut [u8; KEY_LEN]) -> SecretKey {
+        let key = SecretKey(*bytes);
+        bytes.zeroize();
+        key
+    }
+
+    /// Generates a fresh random key from the operating system RNG.
+    pub fn generate() -> SecretKey {
+        let mut key = SecretKey([0u8; KEY_LEN]);
+        OsRng.fill_bytes(&mut key.0);
+        key
+    }
+
+    /// Borrows the raw key bytes for passing to a cipher.
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey([REDACTED])")
+    }
+}
+
+impl ConstantTimeEq for SecretKey {
+    fn ct_eq(&self, other: &SecretKey) -> subtle::Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl PartialEq for SecretKey {
+    fn eq(&self, other: &SecretKey) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for SecretKey {}
+
+/// Compares two byte strings without branching on their contents.
+///
+/// Only the lengths are compared in variable time, since they are public.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    #[test]
+    fn test_from_bytes_wipes_source() {
+        let mut bytes = [0xabu8; KEY_LEN];
+        let key = SecretKey::from_bytes(&mut bytes);
+
+        assert_eq!(bytes, [0u8; KEY_LEN]);
+        assert_eq!(key.as_bytes(), &[0xabu8; KEY_LEN]);
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let key = SecretKey::from_bytes(&mut [0x41u8; KEY_LEN]);
+        let debug = format!("{:?}", key);
+
+        assert_eq!(debug, "SecretKey([REDACTED])");
+        assert!(!debug.contains("65"));
+    }
+
+    #[test]
+    fn test_zeroize_clears_key() {
+        let mut key = SecretKey::generate();
+        key.zeroize();
+        assert_eq!(key.as_bytes(), &[0u8; KEY_LEN]);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"same tag", b"same tag"));
+        assert!(!constant_time_eq(b"same tag", b"same taG"));
+        assert!(!constant_time_eq(b"short", b"longer"));
+        assert!(SecretKey::from_bytes(&mut [1u8; KEY_LEN]) != SecretKey::from_bytes(&mut [2u8; KEY_LEN]));
+    }
+
+    /// Welch's t statistic between two timing samples.
+    fn welch_t(a: &[f64], b: &[f64]) -> f64 {
+        let mean = |xs: &[f64]| xs.iter().sum::<f64>() / xs.len() as f64;
+        let var = |xs: &[f64], m: f64| xs.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (xs.len() - 1) as f64;
+
+        let (mean_a, mean_b) = (mean(a), mean(b));
+        let (var_a, var_b) = (var(a, mean_a), var(b, mean_b));
+        (mean_a - mean_b) / (var_a / a.len() as f64 + var_b / b.len() as f64).sqrt()
+    }
+
+    /// Drops the slowest samples, which are dominated by interrupts and scheduling.
+    fn crop(mut samples: Vec<f64>, percentile: f64) -> Vec<f64> {
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        samples.truncate((samples.len() as f64 * percentile) as usize);
+        samples
+    }
+
+    /// Times `compare` on a matching input against inputs that differ at a random
+    /// position, with the two classes randomly interleaved, and returns Welch's t.
+    fn timing_t_statistic(compare: fn(&[u8], &[u8]) -> bool) -> f64 {
+        const SAMPLES: usize = 10_000;
+        const CALLS_PER_SAMPLE: usize = 16;
+
+        let mut rng = rand::thread_rng();
+        let secret = [0x5au8; 256];
+        let mut fixed = Vec::with_capacity(SAMPLES);
+        let mut random = Vec::with_capacity(SAMPLES);
+
+        for _ in 0..2 * SAMPLES {
+            let use_fixed = rng.gen::<bool>();
+            let mut candidate = secret;
+            if !use_fixed {
+                candidate[rng.gen_range(0..secret.len())] ^= 0xff;
+            }
+
+            let start = Instant::now();
+            for _ in 0..CALLS_PER_SAMPLE {
+                black_box(compare(black_box(&secret), black_box(&candidate)));
+            }
+            let elapsed = start.elapsed().as_nanos() as f64;
+
+            if use_fixed {
+                fixed.push(elapsed);
+            } else {
+                random.push(elapsed);
+            }
+        }
+
+        welch_t(&crop(fixed, 0.9), &crop(random, 0.9))
+    }
+
+    // dudect-style leakage test: a t statistic above 10 means the comparison
+    // time depends on where the inputs differ. Wall-clock timing is unreliable
+    // on loaded machines and in debug builds, so the timing tests only run on
+    // request: `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_constant_time_eq_timing() {
+        let t = timing_t_statistic(constant_time_eq);
+        assert!(t.abs() < 10.0, "timing leak detected: t = {:.2}", t);
+    }
+
+    // The same harness must flag an early-exit comparison, or it proves nothing
+    #[test]
+    #[ignore]
+    fn test_timing_harness_detects_early_exit() {
+        fn early_exit_eq(a: &[u8], b: &[u8]) -> bool {
+            a.iter().zip(b).all(|(x, y)| x == y)
+        }
+
+        let t = timing_t_statistic(early_exit_eq);
+        assert!(t.abs() > 10.0, "harness missed an obvious leak: t = {:.2}", t);
+    }
+}
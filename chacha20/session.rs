@@ -0,0 +1,307 @@
+//! Encrypted transport sessions over any byte stream, such as a `TcpStream`.
+//!
+//! A session runs a Noise handshake (see [`crate::noise`]) and then exchanges
+//! messages framed with a 2-byte big-endian length prefix. Each direction has
+//! its own key and nonce counter, and both sides rekey a direction after every
+//! `rekey_interval` messages, so no key encrypts an unbounded amount of data.
+
+use crate::noise::{CipherState, HandshakeState, NoiseError, Pattern, DH_LEN, TAG_LEN};
+use std::fmt;
+use std::io::{self, Read, Write};
+use x25519_dalek::StaticSecret;
+
+/// Largest frame Noise allows on the wire.
+pub const MAX_MESSAGE_LEN: usize = 65535;
+
+/// Largest plaintext that fits in one frame.
+pub const MAX_PAYLOAD_LEN: usize = MAX_MESSAGE_LEN - TAG_LEN;
+
+/// Messages per direction between rekeys unless configured otherwise.
+pub const DEFAULT_REKEY_INTERVAL: u64 = 1 << 16;
+
+/// Errors returned by a session.
+#[derive(Debug)]
+pub enum SessionError {
+    /// The underlying stream failed or closed.
+    Io(io::Error),
+    /// The handshake or a transport message failed.
+    Noise(NoiseError),
+    /// The message does not fit in a single frame.
+    MessageTooLarge,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(err) => write!(f, "session I/O error: {}", err),
+            SessionError::Noise(err) => write!(f, "{}", err),
+            SessionError::MessageTooLarge => write!(f, "message exceeds {} bytes", MAX_PAYLOAD_LEN),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<io::Error> for SessionError {
+    fn from(err: io::Error) -> SessionError {
+        SessionError::Io(err)
+    }
+}
+
+impl From<NoiseError> for SessionError {
+    fn from(err: NoiseError) -> SessionError {
+        SessionError::Noise(err)
+    }
+}
+
+/// How a session performs its handshake and rekeys.
+pub struct SessionConfig {
+    pattern: Pattern,
+    prologue: Vec<u8>,
+    static_key: Option<StaticSecret>,
+    rekey_interval: u64,
+}
+
+impl SessionConfig {
+    /// Uses `pattern` with an empty prologue and the default rekey interval.
+    pub fn new(pattern: Pattern) -> SessionConfig {
+        SessionConfig {
+            pattern,
+            prologue: Vec::new(),
+            static_key: None,
+            rekey_interval: DEFAULT_REKEY_INTERVAL,
+        }
+    }
+
+    /// Binds the handshake to `prologue`; both sides must use the same value.
+    pub fn with_prologue(mut self, prologue: &[u8]) -> SessionConfig {
+        self.prologue = prologue.to_vec();
+        self
+    }
+
+    /// Sets the long-term key sent by patterns such as XX.
+    pub fn with_static_key(mut self, static_key: StaticSecret) -> SessionConfig {
+        self.static_key = Some(static_key);
+        self
+    }
+
+    /// Rekeys each direction after `interval` messages; both sides must agree.
+    pub fn with_rekey_interval(mut self, interval: u64) -> SessionConfig {
+        self.rekey_interval = interval.max(1);
+        self
+    }
+}
+
+/// An established encrypted session over `S`.
+pub struct Session<S> {
+    stream: S,
+    send: CipherState,
+    receive: CipherState,
+    remote_static: Option<[u8; DH_LEN]>,
+    handshake_hash: [u8; 32],
+    rekey_interval: u64,
+}
+
+fn write_frame<S: Write>(stream: &mut S, frame: &[u8]) -> Result<(), SessionError> {
+    let len = u16::try_from(frame.len()).map_err(|_| SessionError::MessageTooLarge)?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(frame)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_frame<S: Read>(stream: &mut S) -> Result<Vec<u8>, SessionError> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut frame = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+impl<S: Read + Write> Session<S> {
+    /// Performs the handshake as the initiator (the connecting side).
+    pub fn initiate(stream: S, config: SessionConfig) -> Result<Session<S>, SessionError> {
+        Session::handshake(stream, config, true)
+    }
+
+    /// Performs the handshake as the responder (the accepting side).
+    pub fn accept(stream: S, config: SessionConfig) -> Result<Session<S>, SessionError> {
+        Session::handshake(stream, config, false)
+    }
+
+    fn handshake(mut stream: S, config: SessionConfig, initiator: bool) -> Result<Session<S>, SessionError> {
+        let mut handshake = HandshakeState::new(config.pattern, initiator, &config.prologue, config.static_key)?;
+
+        while !handshake.is_finished() {
+            if handshake.is_my_turn() {
+                let message = handshake.write_message(&[])?;
+                write_frame(&mut stream, &message)?;
+            } else {
+                let message = read_frame(&mut stream)?;
+                handshake.read_message(&message)?;
+            }
+        }
+
+        let keys = handshake.into_transport()?;
+        Ok(Session {
+            stream,
+            send: keys.send,
+            receive: keys.receive,
+            remote_static: keys.remote_static,
+            handshake_hash: keys.handshake_hash,
+            rekey_interval: config.rekey_interval,
+        })
+    }
+
+    /// Encrypts and sends one message of at most [`MAX_PAYLOAD_LEN`] bytes.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SessionError> {
+        if message.len() > MAX_PAYLOAD_LEN {
+            return Err(SessionError::MessageTooLarge);
+        }
+
+        let frame = self.send.encrypt_with_ad(&[], message)?;
+        if self.send.nonce().is_multiple_of(self.rekey_interval) {
+            self.send.rekey();
+        }
+        write_frame(&mut self.stream, &frame)
+    }
+
+    /// Receives and decrypts the next message.
+    pub fn receive(&mut self) -> Result<Vec<u8>, SessionError> {
+        let frame = read_frame(&mut self.stream)?;
+        let message = self.receive.decrypt_with_ad(&[], &frame)?;
+        if self.receive.nonce().is_multiple_of(self.rekey_interval) {
+            self.receive.rekey();
+        }
+        Ok(message)
+    }
+
+    /// The peer's static public key, for patterns that transmit one.
+    ///
+    /// The handshake proves the peer holds the matching private key; checking
+    /// that the key is the expected one is up to the caller.
+    pub fn remote_static(&self) -> Option<&[u8; DH_LEN]> {
+        self.remote_static.as_ref()
+    }
+
+    /// The handshake hash, identical on both sides of a session.
+    pub fn handshake_hash(&self) -> &[u8; 32] {
+        &self.handshake_hash
+    }
+
+    /// Returns the underlying stream, ending the session.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use x25519_dalek::PublicKey;
+
+    /// Connects two sockets on loopback and runs `responder` on the accepting end.
+    fn socket_pair<T: Send + 'static>(responder: impl FnOnce(TcpStream) -> T + Send + 'static) -> (TcpStream, thread::JoinHandle<T>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || responder(listener.accept().unwrap().0));
+        (TcpStream::connect(addr).unwrap(), handle)
+    }
+
+    #[test]
+    fn test_nn_session_exchanges_messages() {
+        let (stream, server) = socket_pair(|stream| {
+            let mut session = Session::accept(stream, SessionConfig::new(Pattern::NN)).unwrap();
+            for _ in 0..3 {
+                let message = session.receive().unwrap();
+                session.send(&message.iter().rev().copied().collect::<Vec<u8>>()).unwrap();
+            }
+            *session.handshake_hash()
+        });
+
+        let mut session = Session::initiate(stream, SessionConfig::new(Pattern::NN)).unwrap();
+        for message in [&b"first"[..], b"", b"third message"] {
+            session.send(message).unwrap();
+            let reply = session.receive().unwrap();
+            assert_eq!(reply, message.iter().rev().copied().collect::<Vec<u8>>());
+        }
+        assert_eq!(server.join().unwrap(), *session.handshake_hash());
+    }
+
+    #[test]
+    fn test_xx_session_authenticates_static_keys() {
+        let server_key = StaticSecret::random_from_rng(OsRng);
+        let client_key = StaticSecret::random_from_rng(OsRng);
+        let server_public = PublicKey::from(&server_key).to_bytes();
+        let client_public = PublicKey::from(&client_key).to_bytes();
+
+        let (stream, server) = socket_pair(move |stream| {
+            let config = SessionConfig::new(Pattern::XX).with_static_key(server_key);
+            let mut session = Session::accept(stream, config).unwrap();
+            assert_eq!(session.receive().unwrap(), b"hello");
+            *session.remote_static().unwrap()
+        });
+
+        let config = SessionConfig::new(Pattern::XX).with_static_key(client_key);
+        let mut session = Session::initiate(stream, config).unwrap();
+        session.send(b"hello").unwrap();
+
+        assert_eq!(session.remote_static(), Some(&server_public));
+        assert_eq!(server.join().unwrap(), client_public);
+    }
+
+    #[test]
+    fn test_periodic_rekey_keeps_sides_in_sync() {
+        let (stream, server) = socket_pair(|stream| {
+            let config = SessionConfig::new(Pattern::NN).with_rekey_interval(3);
+            let mut session = Session::accept(stream, config).unwrap();
+            for i in 0..10u8 {
+                assert_eq!(session.receive().unwrap(), [i; 100]);
+                session.send(&[i; 50]).unwrap();
+            }
+        });
+
+        let config = SessionConfig::new(Pattern::NN).with_rekey_interval(3);
+        let mut session = Session::initiate(stream, config).unwrap();
+        for i in 0..10u8 {
+            session.send(&[i; 100]).unwrap();
+            assert_eq!(session.receive().unwrap(), [i; 50]);
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_mismatched_rekey_interval_fails_after_rekey() {
+        let (stream, server) = socket_pair(|stream| {
+            let config = SessionConfig::new(Pattern::NN).with_rekey_interval(2);
+            let mut session = Session::accept(stream, config).unwrap();
+            (0..3).map(|_| session.receive().map(|_| ()).map_err(|e| e.to_string())).collect::<Vec<_>>()
+        });
+
+        let mut session = Session::initiate(stream, SessionConfig::new(Pattern::NN)).unwrap();
+        for _ in 0..3 {
+            session.send(b"message").unwrap();
+        }
+
+        let results = server.join().unwrap();
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(results[2].is_err());
+    }
+
+    #[test]
+    fn test_oversized_message_and_closed_peer() {
+        let (stream, server) = socket_pair(|stream| {
+            let session = Session::accept(stream, SessionConfig::new(Pattern::NN)).unwrap();
+            drop(session.into_inner());
+        });
+
+        let mut session = Session::initiate(stream, SessionConfig::new(Pattern::NN)).unwrap();
+        assert!(matches!(session.send(&vec![0u8; MAX_PAYLOAD_LEN + 1]), Err(SessionError::MessageTooLarge)));
+
+        server.join().unwrap();
+        assert!(matches!(session.receive(), Err(SessionError::Io(_))));
+    }
+}
@@ -0,0 +1,299 @@
+//! STREAM-style chunked ChaCha20-Poly1305 for payloads too large to hold in memory.
+//!
+//! Each chunk is sealed on its own with the nonce `prefix || counter || last`, where
+//! `prefix` is 7 bytes chosen per stream, `counter` is the 32-bit big-endian chunk
+//! index and `last` is `1` only for the final chunk. Reordered or duplicated chunks
+//! fail because their counter no longer matches, and a truncated stream fails
+//! because the last chunk the reader sees was not sealed with the last-chunk flag.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::fmt;
+
+/// Length of the per-stream nonce prefix.
+pub const NONCE_PREFIX_LEN: usize = 7;
+
+/// Length of the Poly1305 tag appended to every chunk.
+pub const TAG_LEN: usize = 16;
+
+/// Errors returned while sealing or opening a chunked stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamError {
+    /// The 32-bit chunk counter would wrap around.
+    CounterOverflow,
+    /// A chunk failed authentication (tampered, reordered, duplicated or misplaced).
+    Authentication,
+    /// The stream ended before a chunk carrying the last-chunk flag was seen.
+    Truncated,
+    /// [`encrypt_chunks`] was asked for chunks of zero bytes.
+    InvalidChunkSize,
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::CounterOverflow => write!(f, "stream chunk counter overflow"),
+            StreamError::Authentication => write!(f, "stream chunk failed authentication"),
+            StreamError::Truncated => write!(f, "stream is truncated"),
+            StreamError::InvalidChunkSize => write!(f, "chunk size must be non-zero"),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// Nonce bookkeeping shared by the encryptor and the decryptor.
+struct NonceCounter {
+    prefix: [u8; NONCE_PREFIX_LEN],
+    counter: u32,
+}
+
+impl NonceCounter {
+    fn new(prefix: &[u8; NONCE_PREFIX_LEN]) -> NonceCounter {
+        NonceCounter { prefix: *prefix, counter: 0 }
+    }
+
+    fn nonce(&self, last: bool) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.prefix);
+        nonce[NONCE_PREFIX_LEN..11].copy_from_slice(&self.counter.to_be_bytes());
+        nonce[11] = last as u8;
+        Nonce::from(nonce)
+    }
+
+    /// Returns the nonce for a non-final chunk and moves on to the next index.
+    fn next(&mut self) -> Result<Nonce, StreamError> {
+        let nonce = self.nonce(false);
+        self.counter = self.counter.checked_add(1).ok_or(StreamError::CounterOverflow)?;
+        Ok(nonce)
+    }
+}
+
+/// Seals a stream chunk by chunk.
+pub struct StreamEncryptor {
+    aead: ChaCha20Poly1305,
+    nonces: NonceCounter,
+    aad: Vec<u8>,
+}
+
+impl StreamEncryptor {
+    /// Creates an encryptor; `nonce_prefix` must never repeat for the same key.
+    pub fn new(key: &[u8; 32], nonce_prefix: &[u8; NONCE_PREFIX_LEN]) -> StreamEncryptor {
+        StreamEncryptor {
+            aead: ChaCha20Poly1305::new(Key::from_slice(key)),
+            nonces: NonceCounter::new(nonce_prefix),
+            aad: Vec::new(),
+        }
+    }
+
+    /// Authenticates `aad` together with every chunk of the stream.
+    pub fn with_associated_data(mut self, aad: &[u8]) -> StreamEncryptor {
+        self.aad = aad.to_vec();
+        self
+    }
+
+    /// Seals a chunk that is followed by at least one more chunk.
+    pub fn encrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>, StreamError> {
+        let nonce = self.nonces.next()?;
+        seal(&self.aead, &nonce, chunk, &self.aad)
+    }
+
+    /// Seals the final chunk and consumes the encryptor.
+    pub fn encrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>, StreamError> {
+        let nonce = self.nonces.nonce(true);
+        seal(&self.aead, &nonce, chunk, &self.aad)
+    }
+}
+
+/// Opens a stream chunk by chunk, releasing plaintext only after each chunk verifies.
+pub struct StreamDecryptor {
+    aead: ChaCha20Poly1305,
+    nonces: NonceCounter,
+    aad: Vec<u8>,
+}
+
+impl StreamDecryptor {
+    /// Creates a decryptor for a stream sealed with the same key and nonce prefix.
+    pub fn new(key: &[u8; 32], nonce_prefix: &[u8; NONCE_PREFIX_LEN]) -> StreamDecryptor {
+        StreamDecryptor {
+            aead: ChaCha20Poly1305::new(Key::from_slice(key)),
+            nonces: NonceCounter::new(nonce_prefix),
+            aad: Vec::new(),
+        }
+    }
+
+    /// Expects `aad` to have been authenticated with every chunk of the stream.
+    pub fn with_associated_data(mut self, aad: &[u8]) -> StreamDecryptor {
+        self.aad = aad.to_vec();
+        self
+    }
+
+    /// Opens a chunk that must be followed by at least one more chunk.
+    pub fn decrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>, StreamError> {
+        let nonce = self.nonces.next()?;
+        open(&self.aead, &nonce, chunk, &self.aad)
+    }
+
+    /// Opens the final chunk and consumes the decryptor.
+    pub fn decrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>, StreamError> {
+        let nonce = self.nonces.nonce(true);
+        open(&self.aead, &nonce, chunk, &self.aad)
+    }
+}
+
+fn seal(aead: &ChaCha20Poly1305, nonce: &Nonce, chunk: &[u8], aad: &[u8]) -> Result<Vec<u8>, StreamError> {
+    aead.encrypt(nonce, Payload { msg: chunk, aad })
+        .map_err(|_| StreamError::Authentication)
+}
+
+fn open(aead: &ChaCha20Poly1305, nonce: &Nonce, chunk: &[u8], aad: &[u8]) -> Result<Vec<u8>, StreamError> {
+    aead.decrypt(nonce, Payload { msg: chunk, aad })
+        .map_err(|_| StreamError::Authentication)
+}
+
+/// Splits `data` into `chunk_size` pieces and seals them as one stream.
+///
+/// An empty input still produces a single (empty) final chunk so that the
+/// reader can tell a complete empty stream from a truncated one. A
+/// `chunk_size` of zero fails with [`StreamError::InvalidChunkSize`].
+pub fn encrypt_chunks(
+    key: &[u8; 32],
+    nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+    data: &[u8],
+    chunk_size: usize,
+) -> Result<Vec<Vec<u8>>, StreamError> {
+    if chunk_size == 0 {
+        return Err(StreamError::InvalidChunkSize);
+    }
+
+    let mut encryptor = StreamEncryptor::new(key, nonce_prefix);
+    let mut chunks: Vec<&[u8]> = data.chunks(chunk_size).collect();
+    let last = chunks.pop().unwrap_or(&[]);
+
+    let mut sealed = Vec::with_capacity(chunks.len() + 1);
+    for chunk in chunks {
+        sealed.push(encryptor.encrypt_next(chunk)?);
+    }
+    sealed.push(encryptor.encrypt_last(last)?);
+    Ok(sealed)
+}
+
+/// Opens a stream produced by [`encrypt_chunks`] and returns the joined plaintext.
+pub fn decrypt_chunks(
+    key: &[u8; 32],
+    nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+    chunks: &[Vec<u8>],
+) -> Result<Vec<u8>, StreamError> {
+    let (last, rest) = chunks.split_last().ok_or(StreamError::Truncated)?;
+
+    let mut decryptor = StreamDecryptor::new(key, nonce_prefix);
+    let mut plaintext = Vec::new();
+    for chunk in rest {
+        plaintext.extend_from_slice(&decryptor.decrypt_next(chunk)?);
+    }
+    plaintext.extend_from_slice(&decryptor.decrypt_last(last)?);
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    fn random_stream_params() -> ([u8; 32], [u8; NONCE_PREFIX_LEN]) {
+        let mut key = [0u8; 32];
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        rand::thread_rng().fill_bytes(&mut key);
+        rand::thread_rng().fill_bytes(&mut prefix);
+        (key, prefix)
+    }
+
+    #[test]
+    fn test_roundtrip_various_lengths() {
+        let (key, prefix) = random_stream_params();
+
+        for len in [0usize, 1, 63, 64, 65, 1000] {
+            let data = vec![0x5au8; len];
+            let chunks = encrypt_chunks(&key, &prefix, &data, 64).unwrap();
+            assert_eq!(chunks.len(), std::cmp::max(1, len.div_ceil(64)));
+            assert!(chunks.iter().all(|c| c.len() <= 64 + TAG_LEN));
+
+            let decrypted = decrypt_chunks(&key, &prefix, &chunks).unwrap();
+            assert_eq!(decrypted, data);
+        }
+    }
+
+    #[test]
+    fn test_zero_chunk_size_is_rejected() {
+        let (key, prefix) = random_stream_params();
+        assert!(matches!(encrypt_chunks(&key, &prefix, b"data", 0), Err(StreamError::InvalidChunkSize)));
+    }
+
+    #[test]
+    fn test_truncation_is_detected() {
+        let (key, prefix) = random_stream_params();
+        let data = vec![7u8; 300];
+        let chunks = encrypt_chunks(&key, &prefix, &data, 100).unwrap();
+
+        // Dropping the final chunk leaves a non-final chunk in the last position
+        let truncated = &chunks[..chunks.len() - 1];
+        assert_eq!(decrypt_chunks(&key, &prefix, truncated), Err(StreamError::Authentication));
+
+        // Dropping everything is reported as truncation, not as an empty plaintext
+        assert_eq!(decrypt_chunks(&key, &prefix, &[]), Err(StreamError::Truncated));
+    }
+
+    #[test]
+    fn test_reordering_is_detected() {
+        let (key, prefix) = random_stream_params();
+        let data: Vec<u8> = (0..=255u8).cycle().take(400).collect();
+        let mut chunks = encrypt_chunks(&key, &prefix, &data, 100).unwrap();
+
+        chunks.swap(0, 1);
+        assert_eq!(decrypt_chunks(&key, &prefix, &chunks), Err(StreamError::Authentication));
+    }
+
+    #[test]
+    fn test_duplication_is_detected() {
+        let (key, prefix) = random_stream_params();
+        let data = vec![1u8; 300];
+        let mut chunks = encrypt_chunks(&key, &prefix, &data, 100).unwrap();
+
+        let duplicate = chunks[1].clone();
+        chunks.insert(2, duplicate);
+        assert_eq!(decrypt_chunks(&key, &prefix, &chunks), Err(StreamError::Authentication));
+
+        // Repeating the final chunk is rejected as well
+        let mut chunks = encrypt_chunks(&key, &prefix, &data, 100).unwrap();
+        let last = chunks.last().unwrap().clone();
+        chunks.push(last);
+        assert_eq!(decrypt_chunks(&key, &prefix, &chunks), Err(StreamError::Authentication));
+    }
+
+    #[test]
+    fn test_associated_data_and_prefix_are_bound() {
+        let (key, prefix) = random_stream_params();
+
+        let mut encryptor = StreamEncryptor::new(&key, &prefix).with_associated_data(b"header v1");
+        let first = encryptor.encrypt_next(b"first").unwrap();
+        let last = encryptor.encrypt_last(b"last").unwrap();
+
+        let mut decryptor = StreamDecryptor::new(&key, &prefix).with_associated_data(b"header v1");
+        assert_eq!(decryptor.decrypt_next(&first).unwrap(), b"first");
+        assert_eq!(decryptor.decrypt_last(&last).unwrap(), b"last");
+
+        let mut wrong_aad = StreamDecryptor::new(&key, &prefix).with_associated_data(b"header v2");
+        assert_eq!(wrong_aad.decrypt_next(&first), Err(StreamError::Authentication));
+
+        let other_prefix = [0xffu8; NONCE_PREFIX_LEN];
+        let mut wrong_prefix = StreamDecryptor::new(&key, &other_prefix).with_associated_data(b"header v1");
+        assert_eq!(wrong_prefix.decrypt_next(&first), Err(StreamError::Authentication));
+    }
+
+    #[test]
+    fn test_counter_overflow() {
+        let mut nonces = NonceCounter::new(&[0u8; NONCE_PREFIX_LEN]);
+        nonces.counter = u32::MAX;
+        assert_eq!(nonces.next(), Err(StreamError::CounterOverflow));
+    }
+}
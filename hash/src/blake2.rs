@@ -0,0 +1,381 @@
+//! BLAKE2b and BLAKE2s (RFC 7693) with the full parameter block.
+//!
+//! The output length, key length, salt and personalization all go into the
+//! initial state, so BLAKE2b-256 is its own function rather than a truncated
+//! BLAKE2b-512. A key turns the hash into a MAC: it is padded to a full block
+//! and processed before the message.
+
+use crate::HashError;
+use zeroize::Zeroize;
+
+/// Output length, key, salt and personalization for BLAKE2b or BLAKE2s.
+///
+/// Salt and personalization shorter than the maximum are padded with zeros.
+#[derive(Clone)]
+pub struct Blake2Params {
+    output_len: usize,
+    key: Vec<u8>,
+    salt: Vec<u8>,
+    personal: Vec<u8>,
+}
+
+impl Blake2Params {
+    /// Unkeyed parameters with an output of `output_len` bytes; the length is
+    /// checked when a hash is built from them.
+    pub fn new(output_len: usize) -> Blake2Params {
+        Blake2Params { output_len, key: Vec::new(), salt: Vec::new(), personal: Vec::new() }
+    }
+
+    /// Keys the hash, turning it into a MAC.
+    pub fn with_key(mut self, key: &[u8]) -> Blake2Params {
+        self.key = key.to_vec();
+        self
+    }
+
+    /// Sets the salt, at most 16 (BLAKE2b) or 8 (BLAKE2s) bytes.
+    pub fn with_salt(mut self, salt: &[u8]) -> Blake2Params {
+        self.salt = salt.to_vec();
+        self
+    }
+
+    /// Sets the personalization, at most 16 (BLAKE2b) or 8 (BLAKE2s) bytes.
+    pub fn with_personal(mut self, personal: &[u8]) -> Blake2Params {
+        self.personal = personal.to_vec();
+        self
+    }
+}
+
+impl Drop for Blake2Params {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// Defines one BLAKE2 variant; the two differ only in word size, round count,
+/// rotations and IV.
+macro_rules! blake2 {
+    (
+        $(#[$doc:meta])*
+        $name:ident, $word:ty, rounds: $rounds:expr, rotations: $rotations:expr, iv: $iv:expr,
+        max_output: $max_output:ident, max_key: $max_key:ident, salt_len: $salt_len:ident
+    ) => {
+        $(#[$doc])*
+        #[derive(Clone)]
+        pub struct $name {
+            h: [$word; 8],
+            /// Bytes compressed so far.
+            t: u128,
+            buffer: [u8; $name::BLOCK_SIZE],
+            buffer_len: usize,
+            output_len: usize,
+        }
+
+        impl $name {
+            /// Bytes per compressed block.
+            pub const BLOCK_SIZE: usize = 16 * WORD_BYTES;
+            const IV: [$word; 8] = $iv;
+
+            /// An unkeyed hash with an output of `output_len` bytes.
+            pub fn new(output_len: usize) -> Result<$name, HashError> {
+                $name::with_params(&Blake2Params::new(output_len))
+            }
+
+            /// A hash set up from `params`, checking every length against the
+            /// limits of this variant.
+            pub fn with_params(params: &Blake2Params) -> Result<$name, HashError> {
+                if !(1..=$max_output).contains(&params.output_len) {
+                    return Err(HashError::InvalidOutputLength(params.output_len));
+                }
+                if params.key.len() > $max_key {
+                    return Err(HashError::InvalidKeyLength(params.key.len()));
+                }
+                if params.salt.len() > $salt_len {
+                    return Err(HashError::InvalidSaltLength(params.salt.len()));
+                }
+                if params.personal.len() > $salt_len {
+                    return Err(HashError::InvalidPersonalLength(params.personal.len()));
+                }
+
+                let mut h = $name::IV;
+                // Digest length, key length, fanout 1 and depth 1
+                h[0] ^= 0x0101_0000 ^ ((params.key.len() as $word) << 8) ^ params.output_len as $word;
+                let mut salt = [0u8; $salt_len];
+                salt[..params.salt.len()].copy_from_slice(&params.salt);
+                let mut personal = [0u8; $salt_len];
+                personal[..params.personal.len()].copy_from_slice(&params.personal);
+                for i in 0..2 {
+                    h[4 + i] ^= <$word>::from_le_bytes(salt[i * WORD_BYTES..(i + 1) * WORD_BYTES].try_into().unwrap());
+                    h[6 + i] ^= <$word>::from_le_bytes(personal[i * WORD_BYTES..(i + 1) * WORD_BYTES].try_into().unwrap());
+                }
+
+                let mut state =
+                    $name { h, t: 0, buffer: [0; $name::BLOCK_SIZE], buffer_len: 0, output_len: params.output_len };
+                if !params.key.is_empty() {
+                    state.buffer[..params.key.len()].copy_from_slice(&params.key);
+                    state.buffer_len = $name::BLOCK_SIZE;
+                }
+                Ok(state)
+            }
+
+            /// Digest length in bytes.
+            pub fn output_len(&self) -> usize {
+                self.output_len
+            }
+
+            /// Feeds `data` into the hash.
+            pub fn update(&mut self, mut data: &[u8]) {
+                while !data.is_empty() {
+                    // The last block is compressed differently, so a full
+                    // buffer waits until more data arrives
+                    if self.buffer_len == $name::BLOCK_SIZE {
+                        self.t += $name::BLOCK_SIZE as u128;
+                        self.compress(false);
+                        self.buffer_len = 0;
+                    }
+                    let take = ($name::BLOCK_SIZE - self.buffer_len).min(data.len());
+                    self.buffer[self.buffer_len..self.buffer_len + take].copy_from_slice(&data[..take]);
+                    self.buffer_len += take;
+                    data = &data[take..];
+                }
+            }
+
+            /// Consumes the hash and returns the `output_len`-byte digest.
+            pub fn finalize(mut self) -> Vec<u8> {
+                self.t += self.buffer_len as u128;
+                self.buffer[self.buffer_len..].fill(0);
+                self.compress(true);
+                self.h.iter().flat_map(|word| word.to_le_bytes()).take(self.output_len).collect()
+            }
+
+            /// Compresses the buffer, which must hold a full (or zero-padded last) block.
+            fn compress(&mut self, last: bool) {
+                let mut m = [0 as $word; 16];
+                for (word, bytes) in m.iter_mut().zip(self.buffer.chunks_exact(WORD_BYTES)) {
+                    *word = <$word>::from_le_bytes(bytes.try_into().unwrap());
+                }
+
+                let mut v = [0 as $word; 16];
+                v[..8].copy_from_slice(&self.h);
+                v[8..].copy_from_slice(&$name::IV);
+                v[12] ^= self.t as $word;
+                v[13] ^= (self.t >> <$word>::BITS) as $word;
+                if last {
+                    v[14] = !v[14];
+                }
+
+                let [r1, r2, r3, r4] = $rotations;
+                let mut g = |a: usize, b: usize, c: usize, d: usize, x: $word, y: $word| {
+                    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+                    v[d] = (v[d] ^ v[a]).rotate_right(r1);
+                    v[c] = v[c].wrapping_add(v[d]);
+                    v[b] = (v[b] ^ v[c]).rotate_right(r2);
+                    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+                    v[d] = (v[d] ^ v[a]).rotate_right(r3);
+                    v[c] = v[c].wrapping_add(v[d]);
+                    v[b] = (v[b] ^ v[c]).rotate_right(r4);
+                };
+                for round in 0..$rounds {
+                    let s = &SIGMA[round % 10];
+                    g(0, 4, 8, 12, m[s[0]], m[s[1]]);
+                    g(1, 5, 9, 13, m[s[2]], m[s[3]]);
+                    g(2, 6, 10, 14, m[s[4]], m[s[5]]);
+                    g(3, 7, 11, 15, m[s[6]], m[s[7]]);
+                    g(0, 5, 10, 15, m[s[8]], m[s[9]]);
+                    g(1, 6, 11, 12, m[s[10]], m[s[11]]);
+                    g(2, 7, 8, 13, m[s[12]], m[s[13]]);
+                    g(3, 4, 9, 14, m[s[14]], m[s[15]]);
+                }
+
+                for i in 0..8 {
+                    self.h[i] ^= v[i] ^ v[i + 8];
+                }
+            }
+        }
+
+        /// The buffer holds the key until the first block is compressed, and
+        /// the chaining value depends on it from then on.
+        impl Drop for $name {
+            fn drop(&mut self) {
+                self.h.zeroize();
+                self.buffer.zeroize();
+            }
+        }
+    };
+}
+
+/// Largest BLAKE2b output and key in bytes.
+pub const BLAKE2B_MAX_OUTPUT_LEN: usize = 64;
+
+/// Length of the BLAKE2b salt and personalization in bytes.
+pub const BLAKE2B_SALT_LEN: usize = 16;
+
+/// Largest BLAKE2s output and key in bytes.
+pub const BLAKE2S_MAX_OUTPUT_LEN: usize = 32;
+
+/// Length of the BLAKE2s salt and personalization in bytes.
+pub const BLAKE2S_SALT_LEN: usize = 8;
+
+mod b {
+    use super::*;
+
+    const WORD_BYTES: usize = 8;
+
+    blake2! {
+        /// BLAKE2b: 64-bit words, 12 rounds, outputs of 1 to 64 bytes.
+        Blake2b, u64, rounds: 12, rotations: [32, 24, 16, 63],
+        iv: [
+            0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+            0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+        ],
+        max_output: BLAKE2B_MAX_OUTPUT_LEN, max_key: BLAKE2B_MAX_OUTPUT_LEN, salt_len: BLAKE2B_SALT_LEN
+    }
+}
+
+mod s {
+    use super::*;
+
+    const WORD_BYTES: usize = 4;
+
+    blake2! {
+        /// BLAKE2s: 32-bit words, 10 rounds, outputs of 1 to 32 bytes.
+        Blake2s, u32, rounds: 10, rotations: [16, 12, 8, 7],
+        iv: [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19],
+        max_output: BLAKE2S_MAX_OUTPUT_LEN, max_key: BLAKE2S_MAX_OUTPUT_LEN, salt_len: BLAKE2S_SALT_LEN
+    }
+}
+
+pub use b::Blake2b;
+pub use s::Blake2s;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blake2::digest::{FixedOutput, KeyInit, Mac, Update, VariableOutput};
+    use blake2::digest::consts::{U16, U32};
+
+    fn sequence(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    fn blake2b(params: &Blake2Params, data: &[u8]) -> String {
+        let mut hasher = Blake2b::with_params(params).unwrap();
+        hasher.update(data);
+        hex::encode(hasher.finalize())
+    }
+
+    fn blake2s(params: &Blake2Params, data: &[u8]) -> String {
+        let mut hasher = Blake2s::with_params(params).unwrap();
+        hasher.update(data);
+        hex::encode(hasher.finalize())
+    }
+
+    // RFC 7693 appendices A and B
+    #[test]
+    fn test_rfc7693_abc() {
+        assert_eq!(
+            blake2b(&Blake2Params::new(64), b"abc"),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        assert_eq!(
+            blake2s(&Blake2Params::new(32), b"abc"),
+            "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982"
+        );
+    }
+
+    // First keyed entries of the reference blake2b-kat.txt and blake2s-kat.txt
+    #[test]
+    fn test_keyed_kat() {
+        assert_eq!(
+            blake2b(&Blake2Params::new(64).with_key(&sequence(64)), b""),
+            "10ebb67700b1868efb4417987acf4690ae9d972fb7a590c2f02871799aaa4786b5e996e8f0f4eb981fc214b005f42d2ff4233499391653df7aefcbc13fc51568"
+        );
+        assert_eq!(
+            blake2s(&Blake2Params::new(32).with_key(&sequence(32)), b""),
+            "48a8997da407876b3d79c0d92325ad3b89cbb754d86ab71aee047ad345fd2c49"
+        );
+    }
+
+    #[test]
+    fn test_blake2b_256_is_not_truncated_blake2b_512() {
+        let full = blake2b(&Blake2Params::new(64), b"Hello, world!");
+        let short = blake2b(&Blake2Params::new(32), b"Hello, world!");
+        assert_eq!(&full[..64], "a2764d133a16816b5847a737a786f2ece4c148095c5faa73e24b4cc5d666c3e4");
+        assert_ne!(short, full[..64]);
+    }
+
+    #[test]
+    fn test_matches_rustcrypto_for_every_length() {
+        // Lengths around the block boundaries exercise the delayed last block
+        for len in [0, 1, 63, 64, 65, 127, 128, 129, 256, 1000] {
+            let data = sequence(len);
+            for output_len in 1..=BLAKE2B_MAX_OUTPUT_LEN {
+                let mut expected = blake2::Blake2bVar::new(output_len).unwrap();
+                expected.update(&data);
+                assert_eq!(blake2b(&Blake2Params::new(output_len), &data), hex::encode(expected.finalize_boxed()));
+            }
+            for output_len in 1..=BLAKE2S_MAX_OUTPUT_LEN {
+                let mut expected = blake2::Blake2sVar::new(output_len).unwrap();
+                expected.update(&data);
+                assert_eq!(blake2s(&Blake2Params::new(output_len), &data), hex::encode(expected.finalize_boxed()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_salt_and_personal_match_rustcrypto() {
+        let data = sequence(300);
+        let key = sequence(40);
+
+        let params = Blake2Params::new(32).with_key(&key).with_salt(b"sixteen byte slt").with_personal(b"my app v1");
+        let mut expected = blake2::Blake2bMac::<U32>::new_with_salt_and_personal(&key, b"sixteen byte slt", b"my app v1")
+            .unwrap();
+        Mac::update(&mut expected, &data);
+        assert_eq!(blake2b(&params, &data), hex::encode(expected.finalize_fixed()));
+
+        let params = Blake2Params::new(16).with_key(&key[..20]).with_salt(b"salt").with_personal(b"persona");
+        let mut expected =
+            blake2::Blake2sMac::<U16>::new_with_salt_and_personal(&key[..20], b"salt", b"persona").unwrap();
+        Mac::update(&mut expected, &data);
+        assert_eq!(blake2s(&params, &data), hex::encode(expected.finalize_fixed()));
+
+        let mut expected = <blake2::Blake2bMac<U32> as KeyInit>::new_from_slice(&key).unwrap();
+        Mac::update(&mut expected, &data);
+        assert_eq!(blake2b(&Blake2Params::new(32).with_key(&key), &data), hex::encode(expected.finalize_fixed()));
+
+        // Salt and personalization each change the output
+        let plain = blake2b(&Blake2Params::new(32), &data);
+        assert_ne!(blake2b(&Blake2Params::new(32).with_salt(b"salt"), &data), plain);
+        assert_ne!(blake2b(&Blake2Params::new(32).with_personal(b"salt"), &data), plain);
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        assert!(matches!(Blake2b::new(0), Err(HashError::InvalidOutputLength(0))));
+        assert!(matches!(Blake2s::new(33), Err(HashError::InvalidOutputLength(33))));
+        assert!(matches!(
+            Blake2b::with_params(&Blake2Params::new(64).with_key(&[0; 65])),
+            Err(HashError::InvalidKeyLength(65))
+        ));
+        assert!(matches!(
+            Blake2s::with_params(&Blake2Params::new(32).with_salt(&[0; 9])),
+            Err(HashError::InvalidSaltLength(9))
+        ));
+        assert!(matches!(
+            Blake2b::with_params(&Blake2Params::new(32).with_personal(&[0; 17])),
+            Err(HashError::InvalidPersonalLength(17))
+        ));
+    }
+}
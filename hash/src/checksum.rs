@@ -0,0 +1,198 @@
+//! Checksum lines in the coreutils formats.
+//!
+//! The default format is `sha256sum`'s `DIGEST  NAME` (`DIGEST *NAME` for
+//! binary mode); `--tag` selects the BSD format `SHA256 (NAME) = DIGEST`.
+//! Names holding a backslash or newline are escaped and the line starts with a
+//! backslash, as coreutils does.
+
+use crate::{Blake2bLen, Digest, HashAlgorithm};
+
+/// Layout of a checksum line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `DIGEST  NAME`, as printed by `sha256sum`.
+    Gnu {
+        /// Marks the file as read in binary mode with `*`.
+        binary: bool,
+    },
+    /// `ALGORITHM (NAME) = DIGEST`, as printed by `sha256sum --tag`.
+    Tag,
+}
+
+/// One parsed line of a checksum list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumLine {
+    pub algorithm: HashAlgorithm,
+    pub expected: Vec<u8>,
+    pub name: String,
+}
+
+/// The algorithm name used in BSD tag lines, such as `SHA256` or `BLAKE2b-256`.
+pub fn tag_name(algorithm: HashAlgorithm) -> String {
+    match algorithm {
+        HashAlgorithm::Blake2b(Blake2bLen::MAX) => String::from("BLAKE2b"),
+        HashAlgorithm::Blake2b(len) => format!("BLAKE2b-{}", len.get() * 8),
+        HashAlgorithm::Blake2s(len) => format!("BLAKE2s-{}", len.get() * 8),
+        _ => algorithm.to_string().to_ascii_uppercase(),
+    }
+}
+
+fn escape(name: &str) -> Option<String> {
+    if !name.contains(['\\', '\n', '\r']) {
+        return None;
+    }
+    Some(name.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r"))
+}
+
+fn unescape(name: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
+
+/// Formats the checksum line for `name`, without a trailing newline.
+pub fn format_line(algorithm: HashAlgorithm, digest: &Digest, name: &str, format: Format) -> String {
+    let (prefix, name) = match escape(name) {
+        Some(escaped) => ("\\", escaped),
+        None => ("", name.to_string()),
+    };
+    match format {
+        Format::Gnu { binary } => {
+            format!("{}{} {}{}", prefix, digest.to_hex(), if binary { '*' } else { ' ' }, name)
+        }
+        Format::Tag => format!("{}{} ({}) = {}", prefix, tag_name(algorithm), name, digest.to_hex()),
+    }
+}
+
+/// Parses a line in either format. Tag lines name their own algorithm; GNU
+/// lines use `default`, except that a BLAKE2 default takes its output length
+/// from the digest, as `b2sum --check` does.
+///
+/// Returns `None` for lines that are not properly formatted.
+pub fn parse_line(line: &str, default: HashAlgorithm) -> Option<ChecksumLine> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let unescape_name = |name: &str| if escaped { unescape(name) } else { Some(name.to_string()) };
+
+    // A GNU name may contain " (", so only a known algorithm makes a tag line
+    let tagged = line.split_once(" (").and_then(|(tag, rest)| Some((tag.parse::<HashAlgorithm>().ok()?, rest)));
+    let (algorithm, digest, name) = if let Some((algorithm, rest)) = tagged {
+        let (name, digest) = rest.rsplit_once(") = ")?;
+        (algorithm, digest, unescape_name(name)?)
+    } else {
+        let (digest, rest) = line.split_once(' ')?;
+        let name = rest.strip_prefix([' ', '*'])?;
+        let algorithm = match default {
+            HashAlgorithm::Blake2b(_) => HashAlgorithm::blake2b(digest.len() / 2).ok()?,
+            HashAlgorithm::Blake2s(_) => HashAlgorithm::blake2s(digest.len() / 2).ok()?,
+            _ => default,
+        };
+        (algorithm, digest, unescape_name(name)?)
+    };
+
+    let expected = hex::decode(digest).ok()?;
+    if expected.len() != algorithm.output_len() || name.is_empty() {
+        return None;
+    }
+    Some(ChecksumLine { algorithm, expected, name })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    fn abc(algorithm: HashAlgorithm) -> Digest {
+        algorithm.hash(b"abc")
+    }
+
+    #[test]
+    fn test_format_matches_coreutils() {
+        let digest = abc(HashAlgorithm::Sha256);
+        assert_eq!(
+            format_line(HashAlgorithm::Sha256, &digest, "abc.txt", Format::Gnu { binary: false }),
+            format!("{}  abc.txt", ABC_SHA256)
+        );
+        assert_eq!(
+            format_line(HashAlgorithm::Sha256, &digest, "-", Format::Gnu { binary: true }),
+            format!("{} *-", ABC_SHA256)
+        );
+        assert_eq!(
+            format_line(HashAlgorithm::Sha256, &digest, "abc.txt", Format::Tag),
+            format!("SHA256 (abc.txt) = {}", ABC_SHA256)
+        );
+        assert_eq!(
+            format_line(HashAlgorithm::Sha256, &digest, "a\\b\nc", Format::Gnu { binary: false }),
+            format!("\\{}  a\\\\b\\nc", ABC_SHA256)
+        );
+
+        assert_eq!(tag_name(HashAlgorithm::Sha3_256), "SHA3-256");
+        assert_eq!(tag_name(HashAlgorithm::Sha512_256), "SHA512-256");
+        assert_eq!(tag_name(HashAlgorithm::Blake2b(Blake2bLen::MAX)), "BLAKE2b");
+        assert_eq!(tag_name(HashAlgorithm::blake2b(32).unwrap()), "BLAKE2b-256");
+    }
+
+    #[test]
+    fn test_lines_round_trip() {
+        let names = ["plain.txt", "with spaces (and parens) = x", "back\\slash", "new\nline", "*star"];
+        let algorithms = [HashAlgorithm::Sha256, HashAlgorithm::Sha3_512, HashAlgorithm::blake2b(32).unwrap()];
+        for algorithm in algorithms {
+            for name in names {
+                for format in [Format::Gnu { binary: false }, Format::Gnu { binary: true }, Format::Tag] {
+                    let digest = abc(algorithm);
+                    let line = format_line(algorithm, &digest, name, format);
+                    let parsed = parse_line(&line, algorithm).unwrap();
+                    assert_eq!(parsed, ChecksumLine { algorithm, expected: digest.into_bytes(), name: name.to_string() });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tag_lines_choose_the_algorithm() {
+        let line = format_line(HashAlgorithm::Sha384, &abc(HashAlgorithm::Sha384), "f", Format::Tag);
+        assert_eq!(parse_line(&line, HashAlgorithm::Sha256).unwrap().algorithm, HashAlgorithm::Sha384);
+    }
+
+    #[test]
+    fn test_blake2_length_comes_from_the_digest() {
+        let blake2b_160 = HashAlgorithm::blake2b(20).unwrap();
+        let line = format_line(blake2b_160, &abc(blake2b_160), "f", Format::Gnu { binary: false });
+        assert_eq!(parse_line(&line, HashAlgorithm::Blake2b(Blake2bLen::MAX)).unwrap().algorithm, blake2b_160);
+    }
+
+    #[test]
+    fn test_improper_lines() {
+        let short = &ABC_SHA256[..62];
+        for line in [
+            String::new(),
+            String::from("no digest here"),
+            format!("{}  f", short),
+            format!("{}  ", ABC_SHA256),
+            format!("{}\tf", ABC_SHA256),
+            format!("{}  f", ABC_SHA256.replace('b', "g")),
+            format!("MD5 (f) = {}", ABC_SHA256),
+            format!("\\{}  bad\\escape", ABC_SHA256),
+        ] {
+            assert_eq!(parse_line(&line, HashAlgorithm::Sha256), None, "{:?}", line);
+        }
+        // Windows line endings are tolerated
+        assert!(parse_line(&format!("{}  f\r", ABC_SHA256), HashAlgorithm::Sha256).is_some());
+    }
+}
@@ -0,0 +1,268 @@
+//! HMAC (RFC 2104) over every [`HashAlgorithm`].
+//!
+//! Keys longer than the hash's block size are hashed first, shorter ones are
+//! padded with zeros. Tags are compared in constant time by [`Hmac::verify`],
+//! or by [`Hmac::verify_truncated`] for the truncated tags of RFC 4231 §4.6.
+
+use crate::{Digest, HashAlgorithm, Hasher};
+use std::fmt;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+/// Shortest truncated tag [`Hmac::verify_truncated`] accepts, in bytes.
+pub const MIN_TAG_LEN: usize = 16;
+
+/// Returned when a tag does not match the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacError;
+
+impl fmt::Display for MacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HMAC tag mismatch")
+    }
+}
+
+impl std::error::Error for MacError {}
+
+/// Incremental HMAC under one key.
+pub struct Hmac {
+    algorithm: HashAlgorithm,
+    inner: Hasher,
+    outer: Hasher,
+}
+
+impl Hmac {
+    /// Starts an HMAC under `key`, which may have any length.
+    pub fn new(algorithm: HashAlgorithm, key: &[u8]) -> Hmac {
+        let mut inner = algorithm.hasher();
+        let mut outer = algorithm.hasher();
+
+        let mut block = vec![0u8; algorithm.block_size()];
+        if key.len() > block.len() {
+            let digest = algorithm.hash(key);
+            block[..digest.as_bytes().len()].copy_from_slice(digest.as_bytes());
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        for (hasher, pad) in [(&mut inner, 0x36), (&mut outer, 0x5c)] {
+            let mut padded: Vec<u8> = block.iter().map(|b| b ^ pad).collect();
+            hasher.update(&padded);
+            padded.zeroize();
+        }
+        block.zeroize();
+        Hmac { algorithm, inner, outer }
+    }
+
+    /// The hash function the HMAC is built on.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Feeds `data` into the message.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Consumes the HMAC and returns the full-length tag.
+    pub fn finalize(mut self) -> Digest {
+        self.outer.update(self.inner.finalize().as_bytes());
+        self.outer.finalize()
+    }
+
+    /// Checks a full-length `tag` against the message in constant time.
+    ///
+    /// A tag of any other length is rejected; use [`Hmac::verify_truncated`]
+    /// for truncated tags.
+    pub fn verify(self, tag: &[u8]) -> Result<(), MacError> {
+        let len = self.algorithm.output_len();
+        self.verify_truncated(tag, len)
+    }
+
+    /// Checks `tag` against the first `len` bytes of the HMAC in constant
+    /// time, as RFC 4231 §4.6 truncation allows.
+    ///
+    /// `len` is the length the protocol fixes, not the length of the received
+    /// tag: the tag must be exactly `len` bytes, and `len` must lie between
+    /// [`MIN_TAG_LEN`] (or the digest length, if shorter) and the digest length.
+    pub fn verify_truncated(self, tag: &[u8], len: usize) -> Result<(), MacError> {
+        let output_len = self.algorithm.output_len();
+        if tag.len() != len || len > output_len || len < MIN_TAG_LEN.min(output_len) {
+            return Err(MacError);
+        }
+        if bool::from(self.finalize().as_bytes()[..len].ct_eq(tag)) {
+            Ok(())
+        } else {
+            Err(MacError)
+        }
+    }
+}
+
+/// Computes the HMAC of `data` in one call.
+pub fn hmac(algorithm: HashAlgorithm, key: &[u8], data: &[u8]) -> Digest {
+    let mut mac = Hmac::new(algorithm, key);
+    mac.update(data);
+    mac.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Blake2bLen, Blake2sLen};
+
+    const SHA2: [HashAlgorithm; 4] =
+        [HashAlgorithm::Sha224, HashAlgorithm::Sha256, HashAlgorithm::Sha384, HashAlgorithm::Sha512];
+
+    const LARGE_DATA: &[u8] = b"This is a test using a larger than block-size key and a larger than block-size data. \
+The key needs to be hashed before being used by the HMAC algorithm.";
+
+    // RFC 4231 section 4; test case 5 is truncated to 128 bits
+    #[test]
+    fn test_rfc4231() {
+        let cases: [(Vec<u8>, Vec<u8>, [&str; 4]); 7] = [
+            (vec![0x0b; 20], b"Hi There".to_vec(), [
+                "896fb1128abbdf196832107cd49df33f47b4b1169912ba4f53684b22",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+                "afd03944d84895626b0825f4ab46907f15f9dadbe4101ec682aa034c7cebc59cfaea9ea9076ede7f4af152e8b2fa9cb6",
+                "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854",
+            ]),
+            (b"Jefe".to_vec(), b"what do ya want for nothing?".to_vec(), [
+                "a30e01098bc6dbbf45690f3a7e9e6d0f8bbea2a39e6148008fd05e44",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e8e2240ca5e69e2c78b3239ecfab21649",
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            ]),
+            (vec![0xaa; 20], vec![0xdd; 50], [
+                "7fb3cb3588c6c1f6ffa9694d7d6ad2649365b0c1f65d69d1ec8333ea",
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+                "88062608d3e6ad8a0aa2ace014c8a86f0aa635d947ac9febe83ef4e55966144b2a5ab39dc13814b94e3ab6e101a34f27",
+                "fa73b0089d56a284efb0f0756c890be9b1b5dbdd8ee81a3655f83e33b2279d39bf3e848279a722c806b485a47e67c807b946a337bee8942674278859e13292fb",
+            ]),
+            ((1..=25).collect(), vec![0xcd; 50], [
+                "6c11506874013cac6a2abc1bb382627cec6a90d86efc012de7afec5a",
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+                "3e8a69b7783c25851933ab6290af6ca77a9981480850009cc5577c6e1f573b4e6801dd23c4a7d679ccf8a386c674cffb",
+                "b0ba465637458c6990e5a8c5f61d4af7e576d97ff94b872de76f8050361ee3dba91ca5c11aa25eb4d679275cc5788063a5f19741120c4f2de2adebeb10a298dd",
+            ]),
+            (vec![0x0c; 20], b"Test With Truncation".to_vec(), [
+                "0e2aea68a90c8d37c988bcdb9fca6fa8",
+                "a3b6167473100ee06e0c796c2955552b",
+                "3abf34c3503b2a23a46efc619baef897",
+                "415fad6271580a531d4179bc891d87a6",
+            ]),
+            (vec![0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(), [
+                "95e9a0db962095adaebe9b2d6f0dbce2d499f112f2d2b7273fa6870e",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+                "4ece084485813e9088d2c63a041bc5b44f9ef1012a2b588f3cd11f05033ac4c60c2ef6ab4030fe8296248df163f44952",
+                "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598",
+            ]),
+            (vec![0xaa; 131], LARGE_DATA.to_vec(), [
+                "3a854166ac5d9f023f54d517d0b39dbd946770db9c2b95c9f6f565d1",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+                "6617178e941f020d351e2f254e8fd32c602420feb0b8fb9adccebb82461e99c5a678cc31e799176d3860e6110c46523e",
+                "e37b6a775dc87dbaa4dfa9f96e5e3ffddebd71f8867289865df5a32d20cdc944b6022cac3c4982b10d5eeb55c3e4de15134676fb6de0446065c97440fa8c6a58",
+            ]),
+        ];
+
+        for (key, data, expected) in cases {
+            for (algorithm, expected) in SHA2.into_iter().zip(expected) {
+                let tag = hmac(algorithm, &key, &data).to_hex();
+                assert_eq!(&tag[..expected.len()], expected, "{}", algorithm);
+
+                let expected = hex::decode(expected).unwrap();
+                let mut mac = Hmac::new(algorithm, &key);
+                mac.update(&data);
+                if expected.len() == algorithm.output_len() {
+                    assert_eq!(mac.verify(&expected), Ok(()));
+                } else {
+                    assert_eq!(mac.verify_truncated(&expected, 16), Ok(()));
+                }
+            }
+        }
+    }
+
+    // Computed with Python's hmac module, which takes the block size from hashlib
+    #[test]
+    fn test_other_algorithms() {
+        let cases = [
+            (HashAlgorithm::Sha512_256, "febab54dd1cba014a5bf1f68e8ce65d98957f47553b5c9209ddf519a7571c98e", None),
+            (
+                HashAlgorithm::Sha3_224,
+                "261f0d514df41b0452e31a1e2f45aab7fdc3b708f709348312b02e26",
+                Some("05d8cd6d00faea8d1eb68ade28730bbd3cbab6929f0a086b29cd62a0"),
+            ),
+            (
+                HashAlgorithm::Sha3_256,
+                "c8766fce050c693aca377aeae5f0580835292a5786f7161bb21da8b3dede8155",
+                Some("65c5b06d4c3de32a7aef8763261e49adb6e2293ec8e7c61e8de61701fc63e123"),
+            ),
+            (
+                HashAlgorithm::Sha3_384,
+                "cd25532989ff1a7281b42535813295729eb3f0b71983fe800087bef0d788d2ccd43bdfe01d5c48a710512cca9640a369",
+                Some("026fdf6b50741e373899c9f7d5406d4eb09fc6665636fc1a530029ddf5cf3ca5a900edce01f5f61e2f408cdf2fd3e7e8"),
+            ),
+            (
+                HashAlgorithm::Sha3_512,
+                "748e03ba2bc23df8045e57bfd889d6a9f24da0be3538e595eae1f9ce0d514652a515254532454384e337e955cbdea82313e3b5a3f729221c92da567d467c8329",
+                Some("38a456a004bd10d32c9ab8336684112862c3db61adcca31829355eaf46fd5c73d06a1f0d13fec9a652fb3811b577b1b1d1b9789f97ae5b83c6f44dfcf1d67eba"),
+            ),
+            (
+                HashAlgorithm::Blake2b(Blake2bLen::MAX),
+                "dbe6457aa93ffb49d56f17e4e9f2ad6df1ee0e00ab9d4b56c7d09391a88eb2f913c462b1513f64813787cef6aefa1c52536a51b69eb33e82b2385e3b4ae530f4",
+                Some("ab347980a64b5e825dd10e7d32fd43a01a8e6dea267ab9ad7d913524526618925311afbcb0c49519cbebdd709540a8d725fb911ac2aee9b2a3aa43d796123393"),
+            ),
+            (HashAlgorithm::blake2b(32).unwrap(), "61dee17440730b873e11029c69c59e57fabc6dee01b7a4c4873538c621899557", None),
+            (
+                HashAlgorithm::Blake2s(Blake2sLen::MAX),
+                "3b2d5d30054ca694b6cb0799718e570363c58d04d2a4ef862cae7dca599e725b",
+                Some("cb60f6a791f140bf8aa2e51ff358cdb2cc5c0333045b7fb77aba7ab3b0cfb237"),
+            ),
+        ];
+        for (algorithm, short_key, long_key) in cases {
+            assert_eq!(hmac(algorithm, b"Jefe", LARGE_DATA).to_hex(), short_key, "{}", algorithm);
+            if let Some(expected) = long_key {
+                assert_eq!(hmac(algorithm, &[0xaa; 131], LARGE_DATA).to_hex(), expected, "{}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_matches_one_shot() {
+        for algorithm in HashAlgorithm::ALL {
+            let mut mac = Hmac::new(algorithm, b"key");
+            for chunk in LARGE_DATA.chunks(7) {
+                mac.update(chunk);
+            }
+            assert_eq!(mac.finalize(), hmac(algorithm, b"key", LARGE_DATA), "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_bad_tags() {
+        let tag = hmac(HashAlgorithm::Sha256, b"key", b"message").into_bytes();
+        let mac = |data: &[u8]| {
+            let mut mac = Hmac::new(HashAlgorithm::Sha256, b"key");
+            mac.update(data);
+            mac
+        };
+        let verify = |data: &[u8], tag: &[u8]| mac(data).verify(tag);
+
+        assert_eq!(verify(b"message", &tag), Ok(()));
+        assert_eq!(verify(b"messagf", &tag), Err(MacError));
+        assert_eq!(verify(b"message", &[]), Err(MacError));
+        // Truncated tags only pass when the caller names the truncation
+        assert_eq!(verify(b"message", &tag[..16]), Err(MacError));
+        assert_eq!(mac(b"message").verify_truncated(&tag[..16], 16), Ok(()));
+        assert_eq!(mac(b"message").verify_truncated(&tag[..20], 20), Ok(()));
+        assert_eq!(mac(b"message").verify_truncated(&tag[..16], 20), Err(MacError));
+        assert_eq!(mac(b"message").verify_truncated(&tag[..15], 15), Err(MacError));
+        assert_eq!(mac(b"messagf").verify_truncated(&tag[..16], 16), Err(MacError));
+        let mut flipped = tag.clone();
+        flipped[31] ^= 1;
+        assert_eq!(verify(b"message", &flipped), Err(MacError));
+        let mut longer = tag.clone();
+        longer.push(0);
+        assert_eq!(verify(b"message", &longer), Err(MacError));
+        assert_eq!(mac(b"message").verify_truncated(&longer, 33), Err(MacError));
+    }
+}
@@ -0,0 +1,439 @@
+//! One hashing API over the SHA-2, SHA-3 and BLAKE2 families.
+//!
+//! A [`HashAlgorithm`] names a function and its output length; hashing returns
+//! the raw digest bytes, which can be encoded as hex or base64 when needed.
+
+pub mod blake2;
+pub mod checksum;
+pub mod hmac;
+pub mod stream;
+
+pub use crate::blake2::{Blake2Params, Blake2b, Blake2s, BLAKE2B_MAX_OUTPUT_LEN, BLAKE2S_MAX_OUTPUT_LEN};
+pub use hmac::{hmac, Hmac, MacError};
+pub use stream::{hash_file, hash_reader};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::digest::DynDigest;
+use std::fmt;
+use std::str::FromStr;
+
+/// A hash function together with its output length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha512_256,
+    Sha3_224,
+    Sha3_256,
+    Sha3_384,
+    Sha3_512,
+    /// BLAKE2b with an output of 1 to 64 bytes.
+    Blake2b(Blake2bLen),
+    /// BLAKE2s with an output of 1 to 32 bytes.
+    Blake2s(Blake2sLen),
+}
+
+/// A BLAKE2b output length of 1 to 64 bytes, built by [`HashAlgorithm::blake2b`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blake2bLen(usize);
+
+impl Blake2bLen {
+    /// The full 64-byte output.
+    pub const MAX: Blake2bLen = Blake2bLen(BLAKE2B_MAX_OUTPUT_LEN);
+
+    /// The length in bytes.
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+/// A BLAKE2s output length of 1 to 32 bytes, built by [`HashAlgorithm::blake2s`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blake2sLen(usize);
+
+impl Blake2sLen {
+    /// The full 32-byte output.
+    pub const MAX: Blake2sLen = Blake2sLen(BLAKE2S_MAX_OUTPUT_LEN);
+
+    /// The length in bytes.
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+/// Errors returned when choosing or parsing a [`HashAlgorithm`] or setting up
+/// BLAKE2 parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashError {
+    /// The output length is not supported by the algorithm.
+    InvalidOutputLength(usize),
+    /// The name does not match any algorithm.
+    UnknownAlgorithm(String),
+    /// The BLAKE2 key is longer than the maximum output length.
+    InvalidKeyLength(usize),
+    /// The BLAKE2 salt is longer than 16 (BLAKE2b) or 8 (BLAKE2s) bytes.
+    InvalidSaltLength(usize),
+    /// The BLAKE2 personalization is longer than 16 (BLAKE2b) or 8 (BLAKE2s) bytes.
+    InvalidPersonalLength(usize),
+}
+
+impl fmt::Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashError::InvalidOutputLength(len) => write!(f, "unsupported output length of {} bytes", len),
+            HashError::UnknownAlgorithm(name) => write!(f, "unknown hash algorithm: {}", name),
+            HashError::InvalidKeyLength(len) => write!(f, "unsupported key length of {} bytes", len),
+            HashError::InvalidSaltLength(len) => write!(f, "unsupported salt length of {} bytes", len),
+            HashError::InvalidPersonalLength(len) => {
+                write!(f, "unsupported personalization length of {} bytes", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HashError {}
+
+impl HashAlgorithm {
+    /// Every fixed-length algorithm plus the full-length BLAKE2 variants.
+    pub const ALL: [HashAlgorithm; 11] = [
+        HashAlgorithm::Sha224,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha384,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Sha512_256,
+        HashAlgorithm::Sha3_224,
+        HashAlgorithm::Sha3_256,
+        HashAlgorithm::Sha3_384,
+        HashAlgorithm::Sha3_512,
+        HashAlgorithm::Blake2b(Blake2bLen::MAX),
+        HashAlgorithm::Blake2s(Blake2sLen::MAX),
+    ];
+
+    /// BLAKE2b with an output of `output_len` bytes.
+    pub fn blake2b(output_len: usize) -> Result<HashAlgorithm, HashError> {
+        if !(1..=BLAKE2B_MAX_OUTPUT_LEN).contains(&output_len) {
+            return Err(HashError::InvalidOutputLength(output_len));
+        }
+        Ok(HashAlgorithm::Blake2b(Blake2bLen(output_len)))
+    }
+
+    /// BLAKE2s with an output of `output_len` bytes.
+    pub fn blake2s(output_len: usize) -> Result<HashAlgorithm, HashError> {
+        if !(1..=BLAKE2S_MAX_OUTPUT_LEN).contains(&output_len) {
+            return Err(HashError::InvalidOutputLength(output_len));
+        }
+        Ok(HashAlgorithm::Blake2s(Blake2sLen(output_len)))
+    }
+
+    /// Digest length in bytes.
+    pub fn output_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha224 | HashAlgorithm::Sha3_224 => 28,
+            HashAlgorithm::Sha256 | HashAlgorithm::Sha512_256 | HashAlgorithm::Sha3_256 => 32,
+            HashAlgorithm::Sha384 | HashAlgorithm::Sha3_384 => 48,
+            HashAlgorithm::Sha512 | HashAlgorithm::Sha3_512 => 64,
+            HashAlgorithm::Blake2b(len) => len.get(),
+            HashAlgorithm::Blake2s(len) => len.get(),
+        }
+    }
+
+    /// Bytes the hash consumes per compression, which HMAC pads keys to.
+    pub fn block_size(self) -> usize {
+        match self {
+            HashAlgorithm::Sha224 | HashAlgorithm::Sha256 | HashAlgorithm::Blake2s(_) => 64,
+            HashAlgorithm::Sha384 | HashAlgorithm::Sha512 | HashAlgorithm::Sha512_256 | HashAlgorithm::Blake2b(_) => 128,
+            // The SHA-3 rate: 1600 bits minus twice the output length
+            HashAlgorithm::Sha3_224 => 144,
+            HashAlgorithm::Sha3_256 => 136,
+            HashAlgorithm::Sha3_384 => 104,
+            HashAlgorithm::Sha3_512 => 72,
+        }
+    }
+
+    /// Starts an incremental hash.
+    pub fn hasher(self) -> Hasher {
+        let state = match self {
+            HashAlgorithm::Sha224 => State::Fixed(Box::new(sha2::Sha224::default())),
+            HashAlgorithm::Sha256 => State::Fixed(Box::new(sha2::Sha256::default())),
+            HashAlgorithm::Sha384 => State::Fixed(Box::new(sha2::Sha384::default())),
+            HashAlgorithm::Sha512 => State::Fixed(Box::new(sha2::Sha512::default())),
+            HashAlgorithm::Sha512_256 => State::Fixed(Box::new(sha2::Sha512_256::default())),
+            HashAlgorithm::Sha3_224 => State::Fixed(Box::new(sha3::Sha3_224::default())),
+            HashAlgorithm::Sha3_256 => State::Fixed(Box::new(sha3::Sha3_256::default())),
+            HashAlgorithm::Sha3_384 => State::Fixed(Box::new(sha3::Sha3_384::default())),
+            HashAlgorithm::Sha3_512 => State::Fixed(Box::new(sha3::Sha3_512::default())),
+            // The length types only hold lengths BLAKE2 accepts
+            HashAlgorithm::Blake2b(len) => State::Blake2b(Blake2b::new(len.get()).unwrap()),
+            HashAlgorithm::Blake2s(len) => State::Blake2s(Blake2s::new(len.get()).unwrap()),
+        };
+        Hasher { state }
+    }
+
+    /// Hashes `data` in one call.
+    pub fn hash(self, data: &[u8]) -> Digest {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::Sha224 => write!(f, "sha224"),
+            HashAlgorithm::Sha256 => write!(f, "sha256"),
+            HashAlgorithm::Sha384 => write!(f, "sha384"),
+            HashAlgorithm::Sha512 => write!(f, "sha512"),
+            HashAlgorithm::Sha512_256 => write!(f, "sha512-256"),
+            HashAlgorithm::Sha3_224 => write!(f, "sha3-224"),
+            HashAlgorithm::Sha3_256 => write!(f, "sha3-256"),
+            HashAlgorithm::Sha3_384 => write!(f, "sha3-384"),
+            HashAlgorithm::Sha3_512 => write!(f, "sha3-512"),
+            HashAlgorithm::Blake2b(len) => write!(f, "blake2b-{}", len.get() * 8),
+            HashAlgorithm::Blake2s(len) => write!(f, "blake2s-{}", len.get() * 8),
+        }
+    }
+}
+
+/// Parses the names printed by `Display`, such as `sha512-256` or
+/// `blake2b-256`. `blake2b` and `blake2s` alone select the full output.
+impl FromStr for HashAlgorithm {
+    type Err = HashError;
+
+    fn from_str(name: &str) -> Result<HashAlgorithm, HashError> {
+        let lowercase = name.to_ascii_lowercase();
+        let unknown = || HashError::UnknownAlgorithm(name.to_string());
+        match lowercase.as_str() {
+            "sha224" => return Ok(HashAlgorithm::Sha224),
+            "sha256" => return Ok(HashAlgorithm::Sha256),
+            "sha384" => return Ok(HashAlgorithm::Sha384),
+            "sha512" => return Ok(HashAlgorithm::Sha512),
+            "sha512-256" => return Ok(HashAlgorithm::Sha512_256),
+            "sha3-224" => return Ok(HashAlgorithm::Sha3_224),
+            "sha3-256" => return Ok(HashAlgorithm::Sha3_256),
+            "sha3-384" => return Ok(HashAlgorithm::Sha3_384),
+            "sha3-512" => return Ok(HashAlgorithm::Sha3_512),
+            "blake2b" => return Ok(HashAlgorithm::Blake2b(Blake2bLen::MAX)),
+            "blake2s" => return Ok(HashAlgorithm::Blake2s(Blake2sLen::MAX)),
+            _ => {}
+        }
+
+        // blake2b-BITS and blake2s-BITS; a bit count that is not a whole
+        // number of bytes names no algorithm
+        let (family, bits) = lowercase.split_once('-').ok_or_else(unknown)?;
+        let bits: usize = bits.parse().map_err(|_| unknown())?;
+        if !bits.is_multiple_of(8) {
+            return Err(unknown());
+        }
+        match family {
+            "blake2b" => HashAlgorithm::blake2b(bits / 8),
+            "blake2s" => HashAlgorithm::blake2s(bits / 8),
+            _ => Err(unknown()),
+        }
+    }
+}
+
+enum State {
+    Fixed(Box<dyn DynDigest>),
+    Blake2b(Blake2b),
+    Blake2s(Blake2s),
+}
+
+/// An incremental hash started by [`HashAlgorithm::hasher`].
+pub struct Hasher {
+    state: State,
+}
+
+impl Hasher {
+    /// Feeds `data` into the hash.
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            State::Fixed(digest) => digest.update(data),
+            State::Blake2b(digest) => digest.update(data),
+            State::Blake2s(digest) => digest.update(data),
+        }
+    }
+
+    /// Consumes the hasher and returns the digest.
+    pub fn finalize(self) -> Digest {
+        let bytes = match self.state {
+            State::Fixed(digest) => digest.finalize().into_vec(),
+            State::Blake2b(digest) => digest.finalize(),
+            State::Blake2s(digest) => digest.finalize(),
+        };
+        Digest(bytes)
+    }
+}
+
+/// Text encodings for digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Lowercase hexadecimal.
+    Hex,
+    /// Standard base64 with padding.
+    Base64,
+}
+
+/// The raw output of a hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Digest(Vec<u8>);
+
+impl Digest {
+    /// The digest bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consumes the digest and returns its bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// The digest as text in `encoding`.
+    pub fn encode(&self, encoding: Encoding) -> String {
+        match encoding {
+            Encoding::Hex => self.to_hex(),
+            Encoding::Base64 => self.to_base64(),
+        }
+    }
+
+    /// The digest as lowercase hex.
+    pub fn to_hex(&self) -> String {
+        hex::encode(&self.0)
+    }
+
+    /// The digest as padded standard base64.
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(&self.0)
+    }
+}
+
+impl AsRef<[u8]> for Digest {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_digest(algorithm: HashAlgorithm, data: &[u8]) -> String {
+        algorithm.hash(data).to_hex()
+    }
+
+    #[test]
+    fn test_hash_sha256() {
+        assert_eq!(
+            hex_digest(HashAlgorithm::Sha256, b"Hello, world!"),
+            "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3"
+        );
+    }
+
+    #[test]
+    fn test_hash_sha3() {
+        assert_eq!(
+            hex_digest(HashAlgorithm::Sha3_256, b"Hello, world!"),
+            "f345a219da005ebe9c1a1eaad97bbf38a10c8473e41d0af7fb617caa0c6aa722"
+        );
+    }
+
+    #[test]
+    fn test_hash_blake2() {
+        // The old hash_blake2 truncated BLAKE2b-512; BLAKE2b-256 is a different function
+        let digest = HashAlgorithm::Blake2b(Blake2bLen::MAX).hash(b"Hello, world!");
+        assert_eq!(
+            hex::encode(&digest.as_bytes()[..32]),
+            "a2764d133a16816b5847a737a786f2ece4c148095c5faa73e24b4cc5d666c3e4"
+        );
+    }
+
+    // "abc" digests from FIPS 180-4, FIPS 202 and RFC 7693
+    #[test]
+    fn test_abc_vectors() {
+        let vectors = [
+            (HashAlgorithm::Sha224, "23097d223405d8228642a477bda255b32aadbce4bda0b3f7e36c9da7"),
+            (HashAlgorithm::Sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (
+                HashAlgorithm::Sha384,
+                "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7",
+            ),
+            (
+                HashAlgorithm::Sha512,
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            ),
+            (HashAlgorithm::Sha512_256, "53048e2681941ef99b2e29b76b4c7dabe4c2d0c634fc6d46e0e2f13107e7af23"),
+            (HashAlgorithm::Sha3_224, "e642824c3f8cf24ad09234ee7d3c766fc9a3a5168d0c94ad73b46fdf"),
+            (HashAlgorithm::Sha3_256, "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"),
+            (
+                HashAlgorithm::Sha3_384,
+                "ec01498288516fc926459f58e2c6ad8df9b473cb0fc08c2596da7cf0e49be4b298d88cea927ac7f539f1edf228376d25",
+            ),
+            (
+                HashAlgorithm::Sha3_512,
+                "b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0",
+            ),
+            (
+                HashAlgorithm::Blake2b(Blake2bLen::MAX),
+                "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+            ),
+            (HashAlgorithm::Blake2s(Blake2sLen::MAX), "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982"),
+        ];
+        for (algorithm, expected) in vectors {
+            let digest = algorithm.hash(b"abc");
+            assert_eq!(digest.to_hex(), expected, "{}", algorithm);
+            assert_eq!(digest.as_bytes().len(), algorithm.output_len());
+        }
+    }
+
+    #[test]
+    fn test_blake2_output_lengths() {
+        // BLAKE2 encodes the output length in its parameter block, so a short
+        // digest is not a prefix of the full one
+        let short = HashAlgorithm::blake2b(32).unwrap().hash(b"abc");
+        let full = HashAlgorithm::blake2b(64).unwrap().hash(b"abc");
+        assert_eq!(short.as_bytes().len(), 32);
+        assert_ne!(short.as_bytes(), &full.as_bytes()[..32]);
+
+        assert_eq!(HashAlgorithm::blake2s(16).unwrap().hash(b"").as_bytes().len(), 16);
+        assert_eq!(HashAlgorithm::blake2b(0), Err(HashError::InvalidOutputLength(0)));
+        assert_eq!(HashAlgorithm::blake2b(65), Err(HashError::InvalidOutputLength(65)));
+        assert_eq!(HashAlgorithm::blake2s(33), Err(HashError::InvalidOutputLength(33)));
+        assert_eq!(HashAlgorithm::blake2s(0), Err(HashError::InvalidOutputLength(0)));
+    }
+
+    #[test]
+    fn test_incremental_matches_one_shot() {
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        for algorithm in HashAlgorithm::ALL {
+            let mut hasher = algorithm.hasher();
+            for chunk in data.chunks(97) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), algorithm.hash(&data), "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        let short = [HashAlgorithm::blake2b(32).unwrap(), HashAlgorithm::blake2s(16).unwrap()];
+        for algorithm in HashAlgorithm::ALL.into_iter().chain(short) {
+            assert_eq!(algorithm.to_string().parse::<HashAlgorithm>(), Ok(algorithm));
+        }
+        assert_eq!("BLAKE2b".parse::<HashAlgorithm>(), Ok(HashAlgorithm::Blake2b(Blake2bLen::MAX)));
+        assert_eq!("blake2b-12".parse::<HashAlgorithm>(), Err(HashError::UnknownAlgorithm(String::from("blake2b-12"))));
+        // Whole bytes out of range report the length in bytes, like HashAlgorithm::blake2b
+        assert_eq!("blake2s-264".parse::<HashAlgorithm>(), Err(HashError::InvalidOutputLength(33)));
+        assert_eq!("md5".parse::<HashAlgorithm>(), Err(HashError::UnknownAlgorithm(String::from("md5"))));
+    }
+
+    #[test]
+    fn test_encodings() {
+        let digest = HashAlgorithm::Sha256.hash(b"abc");
+        assert_eq!(digest.encode(Encoding::Base64), "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=");
+        assert_eq!(digest.encode(Encoding::Hex), digest.to_hex());
+    }
+}
@@ -1,65 +1,244 @@
-use sha2::{Sha256, Digest};
-use sha3::Sha3_256;
-use blake2::{Blake2b512, Digest as BlakeDigest};
-use hex;
-
-fn hash_sha256(input: &[u8]) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(input);
-    let result = hasher.finalize();
-    hex::encode(result)
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::ExitCode;
+use task::checksum::{self, ChecksumLine, Format};
+use task::{hash_reader, Digest, HashAlgorithm};
+
+const USAGE: &str = "\
+Usage:
+  hash [-a ALGORITHM] [--tag | -b] [FILE...]
+  hash [-a ALGORITHM] -c [--quiet | --status] [--strict] [--ignore-missing] [FILE...]
+
+Prints or checks checksums in the format of sha256sum. With no FILE, or when
+FILE is -, reads stdin. The default algorithm is sha256; the others are
+sha224, sha384, sha512, sha512-256, sha3-224, sha3-256, sha3-384, sha3-512,
+blake2b[-BITS] and blake2s[-BITS].
+
+  -a, --algorithm NAME  hash function to use
+      --tag             print BSD-style lines: SHA256 (FILE) = DIGEST
+  -b, --binary          mark files with '*' as read in binary mode
+  -c, --check           verify the checksums listed in the FILEs
+      --quiet           with --check, do not print OK for verified files
+      --status          with --check, print nothing; the exit code tells
+      --strict          with --check, fail on improperly formatted lines
+      --ignore-missing  with --check, skip listed files that do not exist";
+
+struct Options {
+    algorithm: HashAlgorithm,
+    tag: bool,
+    binary: bool,
+    check: bool,
+    quiet: bool,
+    status: bool,
+    strict: bool,
+    ignore_missing: bool,
+    files: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        algorithm: HashAlgorithm::Sha256,
+        tag: false,
+        binary: false,
+        check: false,
+        quiet: false,
+        status: false,
+        strict: false,
+        ignore_missing: false,
+        files: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--algorithm" | "-a" => {
+                let name = args.next().ok_or(format!("{} needs a value", arg))?;
+                options.algorithm = name.parse().map_err(|e| format!("{}", e))?;
+            }
+            "--tag" => options.tag = true,
+            "--binary" | "-b" => options.binary = true,
+            "--text" | "-t" => options.binary = false,
+            "--check" | "-c" => options.check = true,
+            "--quiet" => options.quiet = true,
+            "--status" => options.status = true,
+            "--strict" => options.strict = true,
+            "--ignore-missing" => options.ignore_missing = true,
+            "--" => options.files.extend(args.by_ref().cloned()),
+            "-" => options.files.push(arg.clone()),
+            _ if arg.starts_with('-') => return Err(format!("unknown argument: {}", arg)),
+            _ => options.files.push(arg.clone()),
+        }
+    }
+
+    if options.check && (options.tag || options.binary) {
+        return Err(String::from("--tag and --binary cannot be used with --check"));
+    }
+    if !options.check && (options.quiet || options.status || options.strict || options.ignore_missing) {
+        return Err(String::from("--quiet, --status, --strict and --ignore-missing only apply to --check"));
+    }
+    if options.files.is_empty() {
+        options.files.push(String::from("-"));
+    }
+    Ok(options)
+}
+
+/// Files are read rather than memory-mapped: a file truncated while mapped
+/// (a rotated log, say) would kill the process with SIGBUS instead of being
+/// reported as unreadable.
+fn hash_path(algorithm: HashAlgorithm, path: &str) -> io::Result<Digest> {
+    match path {
+        "-" => hash_reader(algorithm, io::stdin().lock()),
+        _ => hash_reader(algorithm, File::open(path)?),
+    }
 }
 
-fn hash_sha3(input: &[u8]) -> String {
-    let mut hasher = Sha3_256::new();
-    hasher.update(input);
-    let result = hasher.finalize();
-    hex::encode(result)
+/// Prints one checksum line per file; returns whether every file was hashed.
+fn print_checksums(options: &Options) -> bool {
+    let format = if options.tag { Format::Tag } else { Format::Gnu { binary: options.binary } };
+    let mut stdout = io::stdout().lock();
+    let mut ok = true;
+    for path in &options.files {
+        match hash_path(options.algorithm, path) {
+            Ok(digest) => {
+                if writeln!(stdout, "{}", checksum::format_line(options.algorithm, &digest, path, format)).is_err() {
+                    return false;
+                }
+            }
+            Err(err) => {
+                eprintln!("hash: {}: {}", path, err);
+                ok = false;
+            }
+        }
+    }
+    ok
 }
 
-fn hash_blake2(input: &[u8]) -> String {
-    let mut hasher = Blake2b512::new();
-    hasher.update(input);
-    let result = hasher.finalize();
-    hex::encode(&result[..32]) // Truncate to 256 bits (32 bytes)
+/// Totals over every checksum list, reported as warnings like sha256sum does.
+#[derive(Default)]
+struct CheckSummary {
+    verified: usize,
+    mismatched: usize,
+    unreadable: usize,
+    improper: usize,
 }
 
-fn main() {
-    let data = b"Hello, world!";
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", count, if count == 1 { singular } else { plural })
+}
 
-    let sha256_hash = hash_sha256(data);
-    println!("SHA-256: {}", sha256_hash);
+fn check_line(options: &Options, line: &ChecksumLine, summary: &mut CheckSummary) {
+    let digest = match hash_path(line.algorithm, &line.name) {
+        Ok(digest) => digest,
+        Err(err) if options.ignore_missing && err.kind() == io::ErrorKind::NotFound => return,
+        Err(err) => {
+            summary.unreadable += 1;
+            if !options.status {
+                eprintln!("hash: {}: {}", line.name, err);
+                println!("{}: FAILED open or read", line.name);
+            }
+            return;
+        }
+    };
+
+    if digest.as_bytes() == line.expected.as_slice() {
+        summary.verified += 1;
+        if !options.status && !options.quiet {
+            println!("{}: OK", line.name);
+        }
+    } else {
+        summary.mismatched += 1;
+        if !options.status {
+            println!("{}: FAILED", line.name);
+        }
+    }
+}
 
-    let sha3_hash = hash_sha3(data);
-    println!("SHA-3: {}", sha3_hash);
+fn check_list(options: &Options, list: &str, summary: &mut CheckSummary) -> Result<(), String> {
+    let reader: Box<dyn BufRead> = match list {
+        "-" => Box::new(io::stdin().lock()),
+        _ => Box::new(BufReader::new(File::open(list).map_err(|e| format!("{}: {}", list, e))?)),
+    };
 
-    let blake2_hash = hash_blake2(data);
-    println!("Blake2: {}", blake2_hash);
+    let mut found_line = false;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", list, e))?;
+        // Comments and blank lines are not checksum lines
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match checksum::parse_line(&line, options.algorithm) {
+            Some(parsed) => {
+                found_line = true;
+                check_line(options, &parsed, summary);
+            }
+            None => {
+                summary.improper += 1;
+                if !options.status && options.strict {
+                    eprintln!("hash: {}: {}: improperly formatted checksum line", list, index + 1);
+                }
+            }
+        }
+    }
+    if !found_line {
+        return Err(format!("{}: no properly formatted checksum lines found", list));
+    }
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Verifies every listed checksum; returns whether all of them matched.
+fn check(options: &Options) -> bool {
+    let mut summary = CheckSummary::default();
+    let mut ok = true;
+    for list in &options.files {
+        if let Err(err) = check_list(options, list, &mut summary) {
+            eprintln!("hash: {}", err);
+            ok = false;
+        }
+    }
 
-    #[test]
-    fn test_hash_sha256() {
-        let data = b"Hello, world!";
-        let result = hash_sha256(data);
-        assert_eq!(result, "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3");
+    if !options.status {
+        if summary.improper > 0 {
+            eprintln!("hash: WARNING: {} improperly formatted", plural(summary.improper, "line is", "lines are"));
+        }
+        if summary.unreadable > 0 {
+            eprintln!("hash: WARNING: {} could not be read", plural(summary.unreadable, "listed file", "listed files"));
+        }
+        if summary.mismatched > 0 {
+            eprintln!(
+                "hash: WARNING: {} did NOT match",
+                plural(summary.mismatched, "computed checksum", "computed checksums")
+            );
+        }
     }
+    if options.ignore_missing && summary.verified == 0 && ok {
+        if !options.status {
+            eprintln!("hash: no file was verified");
+        }
+        ok = false;
+    }
+    ok && summary.mismatched == 0 && summary.unreadable == 0 && !(options.strict && summary.improper > 0)
+}
 
-    #[test]
-    fn test_hash_sha3() {
-        let data = b"Hello, world!";
-        let result = hash_sha3(data);
-        assert_eq!(result, "f345a219da005ebe9c1a1eaad97bbf38a10c8473e41d0af7fb617caa0c6aa722");
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
     }
 
-    #[test]
-    fn test_hash_blake2() {
-        let data = b"Hello, world!";
-        let expected_hash = "a2764d133a16816b5847a737a786f2ece4c148095c5faa73e24b4cc5d666c3e4";
-        let result = hash_blake2(data);
-        assert_eq!(result, expected_hash);
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("hash: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let ok = if options.check { check(&options) } else { print_checksums(&options) };
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
 }
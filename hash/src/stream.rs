@@ -0,0 +1,167 @@
+//! Hashing readers and files without holding them in memory.
+//!
+//! Readers are consumed through a fixed buffer. Files at least
+//! [`MMAP_THRESHOLD`] bytes long are memory-mapped instead, which saves the
+//! copy into the buffer; smaller files are cheaper to read.
+
+use crate::{Digest, HashAlgorithm, Hasher};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Bytes read at a time when hashing a reader.
+pub const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Files this large or larger are memory-mapped by [`hash_file`].
+pub const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+impl Hasher {
+    /// Hashes everything `reader` yields and returns the number of bytes read.
+    pub fn update_reader<R: Read>(&mut self, mut reader: R) -> io::Result<u64> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut total = 0;
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => return Ok(total),
+                Ok(len) => {
+                    self.update(&buffer[..len]);
+                    total += len as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Lets a hasher be the destination of [`io::copy`] or a `BufWriter`.
+impl Write for Hasher {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.update(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hashes everything `reader` yields.
+pub fn hash_reader<R: Read>(algorithm: HashAlgorithm, reader: R) -> io::Result<Digest> {
+    let mut hasher = algorithm.hasher();
+    hasher.update_reader(reader)?;
+    Ok(hasher.finalize())
+}
+
+/// Hashes the file at `path`, memory-mapping it when it is large.
+///
+/// A mapped file that another process truncates while it is being hashed can
+/// crash the process with SIGBUS; hash files that may change through
+/// [`hash_reader`] instead.
+pub fn hash_file(algorithm: HashAlgorithm, path: impl AsRef<Path>) -> io::Result<Digest> {
+    let mut hasher = algorithm.hasher();
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.is_file() && metadata.len() >= MMAP_THRESHOLD {
+        update_mmap(&mut hasher, &file)?;
+    } else {
+        hasher.update_reader(file)?;
+    }
+    Ok(hasher.finalize())
+}
+
+fn update_mmap(hasher: &mut Hasher, file: &File) -> io::Result<()> {
+    // SAFETY: the mapping is only read, and only while `file` is open. See
+    // hash_file for what happens if the file is truncated meanwhile.
+    let map = unsafe { Mmap::map(file)? };
+    hasher.update(&map);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}", name, std::process::id()))
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    /// A reader that returns a few bytes at a time and is interrupted between reads.
+    struct Trickle {
+        data: Vec<u8>,
+        position: usize,
+        interrupt: bool,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let len = buffer.len().min(13).min(self.data.len() - self.position);
+            buffer[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+            self.position += len;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_reader_matches_one_shot() {
+        let data = sample(3 * BUFFER_SIZE + 17);
+        for algorithm in HashAlgorithm::ALL {
+            let expected = algorithm.hash(&data);
+            assert_eq!(hash_reader(algorithm, &data[..]).unwrap(), expected, "{}", algorithm);
+
+            let trickle = Trickle { data: data[..1000].to_vec(), position: 0, interrupt: false };
+            assert_eq!(hash_reader(algorithm, trickle).unwrap(), algorithm.hash(&data[..1000]));
+        }
+    }
+
+    #[test]
+    fn test_copy_into_hasher() {
+        let data = sample(100_000);
+        let mut hasher = HashAlgorithm::Sha256.hasher();
+        assert_eq!(io::copy(&mut &data[..], &mut hasher).unwrap(), data.len() as u64);
+        assert_eq!(hasher.finalize(), HashAlgorithm::Sha256.hash(&data));
+    }
+
+    #[test]
+    fn test_files_read_and_mapped() {
+        let path = temp_path("hash-stream-file");
+        let data = sample(1_000_003);
+        fs::write(&path, &data).unwrap();
+
+        for algorithm in [HashAlgorithm::Sha512, HashAlgorithm::blake2b(32).unwrap(), HashAlgorithm::Sha3_256] {
+            let expected = algorithm.hash(&data);
+            assert_eq!(hash_file(algorithm, &path).unwrap(), expected);
+
+            let mut hasher = algorithm.hasher();
+            update_mmap(&mut hasher, &File::open(&path).unwrap()).unwrap();
+            assert_eq!(hasher.finalize(), expected);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_large_file_is_mapped() {
+        let path = temp_path("hash-stream-large");
+        let data = sample(MMAP_THRESHOLD as usize + 1);
+        fs::write(&path, &data).unwrap();
+        assert_eq!(hash_file(HashAlgorithm::Sha256, &path).unwrap(), HashAlgorithm::Sha256.hash(&data));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let err = hash_file(HashAlgorithm::Sha256, temp_path("hash-stream-missing")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
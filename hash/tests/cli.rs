@@ -0,0 +1,28 @@
+use std::fs;
+use std::process::Command;
+use task::stream::MMAP_THRESHOLD;
+use task::HashAlgorithm;
+
+// Files above the mmap threshold go through the same read path as small ones
+#[test]
+fn test_large_file_and_check() {
+    let dir = std::env::temp_dir().join(format!("hash-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("large.bin");
+    let data: Vec<u8> = (0..MMAP_THRESHOLD as usize + 4097).map(|i| (i * 31 + i / 509) as u8).collect();
+    fs::write(&path, &data).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hash")).arg(&path).output().unwrap();
+    assert!(output.status.success());
+    let line = String::from_utf8(output.stdout).unwrap();
+    let expected = HashAlgorithm::Sha256.hash(&data).to_hex();
+    assert_eq!(line, format!("{}  {}\n", expected, path.display()));
+
+    let sums = dir.join("SHA256SUMS");
+    fs::write(&sums, &line).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_hash")).arg("-c").arg(&sums).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}: OK\n", path.display()));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
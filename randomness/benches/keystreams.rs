@@ -0,0 +1,46 @@
+//! Prints the battery's p-values for each keystream.
+//!
+//! Run with `cargo bench --bench keystreams`.
+
+use chacha20_cipher::backend;
+use randomness::{run_battery, ALPHA};
+use salsa20::{Salsa20, XSalsa20};
+use std::time::Instant;
+
+/// Keystream bytes per cipher, 2^23 bits.
+const KEYSTREAM_LEN: usize = 1 << 20;
+
+fn report(name: &str, keystream: &[u8]) {
+    let start = Instant::now();
+    let report = run_battery(keystream);
+    println!("{} ({:.2?})", name, start.elapsed());
+    for result in &report.results {
+        let p_values: Vec<String> = result.p_values.iter().map(|p| format!("{:.6}", p)).collect();
+        let verdict = if result.min_p_value() < ALPHA { "FAIL" } else { "ok" };
+        println!("  {:<22} {:<4} {}", result.name, verdict, p_values.join(" "));
+    }
+}
+
+fn main() {
+    let key = [0x9a; 32];
+
+    let mut keystream = vec![0u8; KEYSTREAM_LEN];
+    Salsa20::<20>::new(&key, &[0x5c; 8]).encrypt(&mut keystream);
+    report("Salsa20/20", &keystream);
+
+    let mut keystream = vec![0u8; KEYSTREAM_LEN];
+    Salsa20::<12>::new(&key, &[0x5c; 8]).encrypt(&mut keystream);
+    report("Salsa20/12", &keystream);
+
+    let mut keystream = vec![0u8; KEYSTREAM_LEN];
+    Salsa20::<8>::new(&key, &[0x5c; 8]).encrypt(&mut keystream);
+    report("Salsa20/8", &keystream);
+
+    let mut keystream = vec![0u8; KEYSTREAM_LEN];
+    XSalsa20::new(&key, &[0x5c; 24]).encrypt(&mut keystream);
+    report("XSalsa20", &keystream);
+
+    let mut keystream = vec![0u8; KEYSTREAM_LEN];
+    backend::apply_keystream(&key, &[0x5c; 12], 0, &mut keystream);
+    report("ChaCha20", &keystream);
+}
@@ -0,0 +1,434 @@
+//! Statistical tests for keystream quality.
+//!
+//! The battery combines the FIPS 140-1 / Menezes et al. tests (monobit, runs,
+//! poker, serial, autocorrelation) with a subset of NIST SP 800-22 (block
+//! frequency, longest run of ones, approximate entropy and cumulative sums).
+//! Every test returns p-values; a keystream fails when any p-value falls below
+//! the significance level. Passing proves nothing about security, but a
+//! broken cipher core usually fails badly.
+
+mod special;
+
+use special::{erfc, igamc, normal_cdf};
+use std::f64::consts::{LN_2, SQRT_2};
+
+/// Significance level used by [`Report::passed`].
+pub const ALPHA: f64 = 0.001;
+
+/// Bits the battery needs for every test to apply; 2^20 is comfortable.
+pub const MIN_BITS: usize = 1 << 16;
+
+/// Shifts checked by the autocorrelation test.
+const AUTOCORRELATION_SHIFTS: [usize; 8] = [1, 2, 3, 8, 16, 32, 64, 512];
+
+/// Splits bytes into bits, most significant bit first.
+pub fn bits(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1)).collect()
+}
+
+/// NIST 2.1: the proportion of ones should be close to one half.
+pub fn monobit(bits: &[u8]) -> f64 {
+    let n = bits.len() as f64;
+    let sum: i64 = bits.iter().map(|&b| 2 * b as i64 - 1).sum();
+    erfc(sum.unsigned_abs() as f64 / n.sqrt() / SQRT_2)
+}
+
+/// NIST 2.2: the proportion of ones within each `block_len`-bit block.
+///
+/// Returns `None` unless `1 <= block_len <= bits.len()`.
+pub fn block_frequency(bits: &[u8], block_len: usize) -> Option<f64> {
+    if block_len == 0 || block_len > bits.len() {
+        return None;
+    }
+    let blocks = bits.len() / block_len;
+    let chi_squared: f64 = bits
+        .chunks_exact(block_len)
+        .map(|block| {
+            let pi = block.iter().map(|&b| b as f64).sum::<f64>() / block_len as f64;
+            (pi - 0.5) * (pi - 0.5)
+        })
+        .sum::<f64>()
+        * 4.0
+        * block_len as f64;
+    Some(igamc(blocks as f64 / 2.0, chi_squared / 2.0))
+}
+
+/// NIST 2.3: the number of runs of identical bits.
+pub fn runs(bits: &[u8]) -> f64 {
+    let n = bits.len() as f64;
+    let pi = bits.iter().map(|&b| b as f64).sum::<f64>() / n;
+    // The test only applies once the monobit frequency is plausible
+    if (pi - 0.5).abs() >= 2.0 / n.sqrt() {
+        return 0.0;
+    }
+
+    let runs = 1 + bits.windows(2).filter(|pair| pair[0] != pair[1]).count();
+    let expected = 2.0 * n * pi * (1.0 - pi);
+    erfc((runs as f64 - expected).abs() / (2.0 * (2.0 * n).sqrt() * pi * (1.0 - pi)))
+}
+
+/// NIST 2.4: the longest run of ones within blocks of 8, 128 or 10^4 bits,
+/// depending on the sequence length (at least 128 bits).
+pub fn longest_run_of_ones(bits: &[u8]) -> f64 {
+    let (block_len, min_class, probabilities): (usize, usize, &[f64]) = if bits.len() >= 750_000 {
+        (10_000, 10, &[0.0882, 0.2092, 0.2483, 0.1933, 0.1208, 0.0675, 0.0727])
+    } else if bits.len() >= 6272 {
+        (128, 4, &[0.1174, 0.2430, 0.2493, 0.1752, 0.1027, 0.1124])
+    } else {
+        (8, 1, &[0.2148, 0.3672, 0.2305, 0.1875])
+    };
+    let classes = probabilities.len();
+
+    let mut counts = vec![0usize; classes];
+    for block in bits.chunks_exact(block_len) {
+        let mut longest = 0;
+        let mut current = 0;
+        for &bit in block {
+            current = if bit == 1 { current + 1 } else { 0 };
+            longest = longest.max(current);
+        }
+        counts[longest.clamp(min_class, min_class + classes - 1) - min_class] += 1;
+    }
+
+    let blocks = (bits.len() / block_len) as f64;
+    let chi_squared: f64 = counts
+        .iter()
+        .zip(probabilities)
+        .map(|(&count, &p)| (count as f64 - blocks * p).powi(2) / (blocks * p))
+        .sum();
+    igamc((classes - 1) as f64 / 2.0, chi_squared / 2.0)
+}
+
+/// FIPS 140-1 poker test: the distribution of 4-bit nibbles.
+pub fn poker(bits: &[u8]) -> f64 {
+    let mut counts = [0usize; 16];
+    for nibble in bits.chunks_exact(4) {
+        counts[nibble.iter().fold(0, |acc, &b| acc << 1 | b as usize)] += 1;
+    }
+    let k = (bits.len() / 4) as f64;
+    let x = 16.0 / k * counts.iter().map(|&c| (c * c) as f64).sum::<f64>() - k;
+    igamc(15.0 / 2.0, x / 2.0)
+}
+
+/// Whether `m`-bit patterns fit `bits`: at least one bit per pattern and no
+/// more possible patterns than bits.
+fn valid_pattern_len(bits: &[u8], m: usize) -> bool {
+    m > 0
+        && u32::try_from(m)
+            .ok()
+            .and_then(|m| 1usize.checked_shl(m))
+            .is_some_and(|patterns| patterns <= bits.len())
+}
+
+/// Frequencies of every overlapping `m`-bit pattern, wrapping around the end.
+fn pattern_counts(bits: &[u8], m: usize) -> Vec<usize> {
+    let mut counts = vec![0usize; 1 << m];
+    if m == 0 {
+        return counts;
+    }
+    let n = bits.len();
+    for i in 0..n {
+        let pattern = (0..m).fold(0, |acc, j| acc << 1 | bits[(i + j) % n] as usize);
+        counts[pattern] += 1;
+    }
+    counts
+}
+
+/// NIST 2.11: the frequencies of all overlapping `m`-bit patterns. Returns
+/// the two p-values of the first and second differences of psi-squared.
+///
+/// Returns `None` unless `1 <= m` and `2^m <= bits.len()`.
+pub fn serial(bits: &[u8], m: usize) -> Option<[f64; 2]> {
+    if !valid_pattern_len(bits, m) {
+        return None;
+    }
+    let n = bits.len() as f64;
+    let psi_squared = |m: usize| -> f64 {
+        if m == 0 {
+            return 0.0;
+        }
+        let sum: f64 = pattern_counts(bits, m).iter().map(|&c| (c * c) as f64).sum();
+        (1u64 << m) as f64 / n * sum - n
+    };
+
+    let psi_m = psi_squared(m);
+    let psi_m1 = psi_squared(m - 1);
+    let psi_m2 = if m >= 2 { psi_squared(m - 2) } else { 0.0 };
+    let delta = psi_m - psi_m1;
+    let delta2 = psi_m - 2.0 * psi_m1 + psi_m2;
+    Some([
+        igamc((1u64 << (m - 1)) as f64 / 2.0, delta / 2.0),
+        igamc((1u64 << (m - 1)) as f64 / 4.0, delta2 / 2.0),
+    ])
+}
+
+/// NIST 2.12: compares the frequencies of overlapping `m`- and `m + 1`-bit patterns.
+///
+/// Returns `None` unless `1 <= m` and `2^m <= bits.len()`.
+pub fn approximate_entropy(bits: &[u8], m: usize) -> Option<f64> {
+    if !valid_pattern_len(bits, m) {
+        return None;
+    }
+    let n = bits.len() as f64;
+    let phi = |m: usize| -> f64 {
+        pattern_counts(bits, m)
+            .iter()
+            .filter(|&&c| c > 0)
+            .map(|&c| {
+                let c = c as f64 / n;
+                c * c.ln()
+            })
+            .sum()
+    };
+    let apen = phi(m) - phi(m + 1);
+    let chi_squared = 2.0 * n * (LN_2 - apen);
+    Some(igamc((1u64 << (m - 1)) as f64, chi_squared / 2.0))
+}
+
+/// NIST 2.13: the maximal excursion of the running sum of +1/-1 steps,
+/// taken forwards (`reverse == false`) or backwards.
+pub fn cumulative_sums(bits: &[u8], reverse: bool) -> f64 {
+    let n = bits.len() as f64;
+    let mut sum = 0i64;
+    let mut z = 0u64;
+    let steps: Box<dyn Iterator<Item = &u8>> = if reverse { Box::new(bits.iter().rev()) } else { Box::new(bits.iter()) };
+    for &bit in steps {
+        sum += 2 * bit as i64 - 1;
+        z = z.max(sum.unsigned_abs());
+    }
+    let z = z as f64;
+
+    let term = |k: f64, a: f64, b: f64| normal_cdf((4.0 * k + a) * z / n.sqrt()) - normal_cdf((4.0 * k + b) * z / n.sqrt());
+    let mut sum1 = 0.0;
+    let mut k = ((-n / z + 1.0) / 4.0).trunc();
+    while k <= (n / z - 1.0) / 4.0 {
+        sum1 += term(k, 1.0, -1.0);
+        k += 1.0;
+    }
+    let mut sum2 = 0.0;
+    let mut k = ((-n / z - 3.0) / 4.0).trunc();
+    while k <= (n / z - 1.0) / 4.0 {
+        sum2 += term(k, 3.0, 1.0);
+        k += 1.0;
+    }
+    1.0 - sum1 + sum2
+}
+
+/// Autocorrelation test (Menezes et al. 5.4.4): bits `shift` apart should
+/// differ half of the time.
+///
+/// Returns `None` if `shift` leaves no pairs to compare.
+pub fn autocorrelation(bits: &[u8], shift: usize) -> Option<f64> {
+    if shift >= bits.len() {
+        return None;
+    }
+    let compared = (bits.len() - shift) as f64;
+    let differences = bits.iter().zip(&bits[shift..]).filter(|(a, b)| a != b).count() as f64;
+    let x = 2.0 * (differences - compared / 2.0) / compared.sqrt();
+    Some(erfc(x.abs() / SQRT_2))
+}
+
+/// The p-values one test produced.
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub p_values: Vec<f64>,
+}
+
+impl TestResult {
+    fn new(name: impl Into<String>, p_values: Vec<f64>) -> TestResult {
+        TestResult { name: name.into(), p_values }
+    }
+
+    /// The smallest p-value of the test.
+    pub fn min_p_value(&self) -> f64 {
+        self.p_values.iter().copied().fold(1.0, f64::min)
+    }
+}
+
+/// Results of the whole battery over one sequence.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub results: Vec<TestResult>,
+}
+
+impl Report {
+    /// Tests with a p-value below `alpha`.
+    pub fn failures(&self, alpha: f64) -> Vec<&TestResult> {
+        self.results.iter().filter(|result| result.min_p_value() < alpha).collect()
+    }
+
+    /// Whether every test passed at the [`ALPHA`] significance level.
+    pub fn passed(&self) -> bool {
+        self.failures(ALPHA).is_empty()
+    }
+}
+
+/// Runs every test over `bytes`, which must hold at least [`MIN_BITS`] bits.
+pub fn run_battery(bytes: &[u8]) -> Report {
+    let bits = bits(bytes);
+    assert!(bits.len() >= MIN_BITS, "the battery needs at least {} bits", MIN_BITS);
+
+    // MIN_BITS is far more than the block and pattern lengths and shifts below need
+    let [serial_1, serial_2] = serial(&bits, 8).unwrap();
+    let results = vec![
+        TestResult::new("monobit", vec![monobit(&bits)]),
+        TestResult::new("block frequency", vec![block_frequency(&bits, 128).unwrap()]),
+        TestResult::new("runs", vec![runs(&bits)]),
+        TestResult::new("longest run of ones", vec![longest_run_of_ones(&bits)]),
+        TestResult::new("poker", vec![poker(&bits)]),
+        TestResult::new("serial", vec![serial_1, serial_2]),
+        TestResult::new("approximate entropy", vec![approximate_entropy(&bits, 8).unwrap()]),
+        TestResult::new("cumulative sums", vec![cumulative_sums(&bits, false), cumulative_sums(&bits, true)]),
+        TestResult::new(
+            "autocorrelation",
+            AUTOCORRELATION_SHIFTS.iter().map(|&shift| autocorrelation(&bits, shift).unwrap()).collect(),
+        ),
+    ];
+    Report { results }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chacha20_cipher::backend::{self, Backend};
+    use chacha20_cipher::cipher::{Cipher, R20};
+    use salsa20::{Salsa20, XSalsa20};
+
+    fn parse_bits(s: &str) -> Vec<u8> {
+        s.bytes().map(|b| b - b'0').collect()
+    }
+
+    fn assert_p(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "p-value {} != {}", actual, expected);
+    }
+
+    // Worked examples from NIST SP 800-22 rev. 1a, section 2
+    #[test]
+    fn test_nist_examples() {
+        assert_p(monobit(&parse_bits("1011010101")), 0.527089);
+        assert_p(block_frequency(&parse_bits("0110011010"), 3).unwrap(), 0.801252);
+        assert_p(runs(&parse_bits("1001101011")), 0.147232);
+        assert_p(cumulative_sums(&parse_bits("1011010111"), false), 0.4116588);
+        assert_p(approximate_entropy(&parse_bits("0100110101"), 3).unwrap(), 0.261961);
+
+        let [p1, p2] = serial(&parse_bits("0011011101"), 3).unwrap();
+        assert_p(p1, 0.808792);
+        assert_p(p2, 0.670320);
+
+        let longest_run_example = parse_bits(
+            "11001100000101010110110001001100111000000000001001001101010100010001001111010110100000001101011111001100111001101101100010110010",
+        );
+        // The paper prints 0.180609 from a rounded chi-squared; sts computes 0.180598
+        assert_p(longest_run_of_ones(&longest_run_example), 0.180598);
+    }
+
+    #[test]
+    fn test_out_of_range_parameters() {
+        let bits = parse_bits("0011011101");
+        assert_eq!(block_frequency(&bits, 0), None);
+        assert_eq!(block_frequency(&bits, 11), None);
+        assert!(block_frequency(&bits, 10).is_some());
+        assert_eq!(serial(&bits, 0), None);
+        assert_eq!(serial(&bits, 4), None);
+        assert_eq!(serial(&bits, usize::MAX), None);
+        assert_eq!(approximate_entropy(&bits, 0), None);
+        assert_eq!(approximate_entropy(&[], 1), None);
+        assert_eq!(autocorrelation(&bits, 10), None);
+        assert_eq!(autocorrelation(&bits, 11), None);
+        assert!(autocorrelation(&bits, 9).is_some());
+    }
+
+    #[test]
+    fn test_structured_sequences_fail() {
+        let constant = vec![0u8; MIN_BITS / 8];
+        let alternating = vec![0x55u8; MIN_BITS / 8];
+        let counter: Vec<u8> = (0..MIN_BITS / 8).map(|i| i as u8).collect();
+
+        for (name, bytes) in [("constant", constant), ("alternating", alternating), ("counter", counter)] {
+            assert!(!run_battery(&bytes).passed(), "{} sequence passed", name);
+        }
+    }
+
+    /// The original Salsa20 code: ChaCha-style quarter-rounds on a single
+    /// column, no feedforward and the key in words 4-11.
+    fn broken_salsa20_keystream(key: &[u8; 32], nonce: &[u8; 8], len: usize) -> Vec<u8> {
+        let mut state = [0u32; 16];
+        state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+        for i in 0..8 {
+            state[4 + i] = u32::from_le_bytes(key[i * 4..(i + 1) * 4].try_into().unwrap());
+        }
+        state[13] = u32::from_le_bytes(nonce[0..4].try_into().unwrap());
+        state[14] = u32::from_le_bytes(nonce[4..8].try_into().unwrap());
+
+        let mut keystream = Vec::with_capacity(len);
+        while keystream.len() < len {
+            let mut x = state;
+            for _ in 0..20 {
+                x[0] = x[0].wrapping_add(x[4]);
+                x[12] = (x[12] ^ x[0]).rotate_left(16);
+                x[8] = x[8].wrapping_add(x[12]);
+                x[4] = (x[4] ^ x[8]).rotate_left(12);
+                x[0] = x[0].wrapping_add(x[4]);
+                x[12] = (x[12] ^ x[0]).rotate_left(8);
+                x[8] = x[8].wrapping_add(x[12]);
+                x[4] = (x[4] ^ x[8]).rotate_left(7);
+            }
+            keystream.extend(x.iter().flat_map(|word| word.to_le_bytes()));
+            state[12] = state[12].wrapping_add(1);
+        }
+        keystream.truncate(len);
+        keystream
+    }
+
+    #[test]
+    fn test_broken_salsa20_core_fails() {
+        let keystream = broken_salsa20_keystream(&[0x42; 32], &[0x24; 8], KEYSTREAM_LEN);
+        assert!(!run_battery(&keystream).passed());
+    }
+
+    /// Keystream bytes checked per cipher, 2^20 bits.
+    const KEYSTREAM_LEN: usize = 1 << 17;
+
+    /// Fixed inputs keep the outcome deterministic; the all-zero key and nonce
+    /// are the least random inputs a cipher can get.
+    const INPUTS: [([u8; 32], u8); 2] = [([0u8; 32], 0), ([0x9a; 32], 0x5c)];
+
+    fn assert_random(name: &str, keystream: &[u8]) {
+        let report = run_battery(keystream);
+        let failures = report.failures(ALPHA);
+        assert!(failures.is_empty(), "{} keystream failed: {:?}", name, failures);
+    }
+
+    #[test]
+    fn test_salsa20_keystreams_are_random() {
+        for (key, nonce_byte) in INPUTS {
+            let mut keystream = vec![0u8; KEYSTREAM_LEN];
+            Salsa20::<20>::new(&key, &[nonce_byte; 8]).encrypt(&mut keystream);
+            assert_random("Salsa20/20", &keystream);
+
+            let mut keystream = vec![0u8; KEYSTREAM_LEN];
+            Salsa20::<8>::new(&key, &[nonce_byte; 8]).encrypt(&mut keystream);
+            assert_random("Salsa20/8", &keystream);
+
+            let mut keystream = vec![0u8; KEYSTREAM_LEN];
+            XSalsa20::new(&key, &[nonce_byte; 24]).encrypt(&mut keystream);
+            assert_random("XSalsa20", &keystream);
+        }
+    }
+
+    #[test]
+    fn test_chacha20_keystreams_are_random() {
+        for (key, nonce_byte) in INPUTS {
+            let mut keystream = vec![0u8; KEYSTREAM_LEN];
+            Cipher::<R20>::new(&key, &[nonce_byte; 12]).apply_keystream(&mut keystream);
+            assert_random("ChaCha20", &keystream);
+
+            for backend in Backend::ALL.into_iter().filter(|b| b.is_available()) {
+                let mut keystream = vec![0u8; KEYSTREAM_LEN];
+                backend::apply_keystream_with(backend, &key, &[nonce_byte; 12], 0, &mut keystream);
+                assert_random(&format!("ChaCha20 {:?}", backend), &keystream);
+            }
+        }
+    }
+}
@@ -0,0 +1,717 @@
+// src/lib.rs
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::fmt;
+
+#[cfg(feature = "std")]
+pub mod nonce_log;
+#[cfg(feature = "alloc")]
+pub mod scrypt;
+#[cfg(feature = "alloc")]
+pub mod secretbox;
+mod traits;
+pub mod xsalsa20;
+
+pub use xsalsa20::{hsalsa20, XSalsa20};
+
+/// Round count of the full cipher, the default for [`Salsa20`].
+pub const ROUNDS: usize = 20;
+
+/// Size of one Salsa20 keystream block in bytes.
+const BLOCK_SIZE: usize = 64;
+
+/// Length of the keystream for one key and nonce: 2^64 blocks of 64 bytes.
+const KEYSTREAM_LEN: u128 = 1 << 70;
+
+/// "expand 32-byte k", placed on the diagonal of the state.
+const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+/// Errors returned by [`Salsa20::try_encrypt`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Salsa20Error {
+    /// The input runs past the end of the 64-bit block counter.
+    CounterOverflow,
+}
+
+impl fmt::Display for Salsa20Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Salsa20Error::CounterOverflow => write!(f, "Salsa20 block counter overflow"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Salsa20Error {}
+
+/// The Salsa20 stream cipher with `R` rounds.
+///
+/// `R` must be 8, 12 or 20, the eSTREAM Salsa20/8, Salsa20/12 and Salsa20/20
+/// profiles. Without an explicit parameter the type is the full Salsa20/20,
+/// e.g. `let cipher: Salsa20 = Salsa20::<20>::new(&key, &nonce);`, while
+/// `Salsa20::<8>::new(&key, &nonce)` selects Salsa20/8.
+pub struct Salsa20<const R: usize = ROUNDS> {
+    state: [u32; 16],
+    /// Keystream block for the counter preceding the one in `state`.
+    block: [u8; BLOCK_SIZE],
+    /// Bytes of `block` already used; `BLOCK_SIZE` when a new block is needed.
+    offset: usize,
+    /// Set once the block for counter `u64::MAX` has been generated.
+    exhausted: bool,
+}
+
+impl<const R: usize> Salsa20<R> {
+    const VALID_ROUNDS: () = assert!(R == 8 || R == 12 || R == 20, "Salsa20 supports 8, 12 or 20 rounds");
+
+    /// Sets up the state for `key` and `nonce` with the block counter at zero.
+    ///
+    /// Words 0, 5, 10 and 15 hold the constants, 1-4 and 11-14 the key,
+    /// 6-7 the nonce and 8-9 the 64-bit little-endian block counter.
+    pub fn new(key: &[u8; 32], nonce: &[u8; 8]) -> Salsa20<R> {
+        let () = Self::VALID_ROUNDS;
+
+        // The counter in words 8 and 9 starts at zero
+        let mut input = [0u8; 16];
+        input[..8].copy_from_slice(nonce);
+        let state = initial_state(key, &input);
+
+        Salsa20 { state, block: [0u8; BLOCK_SIZE], offset: BLOCK_SIZE, exhausted: false }
+    }
+
+    /// XORs the keystream into `input`, continuing where the previous call
+    /// stopped, so splitting a message across calls gives the same result.
+    ///
+    /// Panics if `input` runs past the end of the keystream; use
+    /// [`Salsa20::try_encrypt`] to handle that case.
+    pub fn encrypt(&mut self, input: &mut [u8]) {
+        self.try_encrypt(input).expect("Salsa20 block counter overflow");
+    }
+
+    /// Like [`Salsa20::encrypt`], but returns an error instead of panicking
+    /// when `input` is longer than the remaining keystream. Nothing is
+    /// encrypted in that case.
+    pub fn try_encrypt(&mut self, input: &mut [u8]) -> Result<(), Salsa20Error> {
+        if input.len() as u128 > self.remaining() {
+            return Err(Salsa20Error::CounterOverflow);
+        }
+
+        // Use up what is left of the buffered block first
+        let buffered = input.len().min(BLOCK_SIZE - self.offset);
+        let (head, rest) = input.split_at_mut(buffered);
+        xor_in_place(head, &self.block[self.offset..self.offset + buffered]);
+        self.offset += buffered;
+
+        // Whole blocks are XORed straight into the caller's buffer
+        let mut chunks = rest.chunks_exact_mut(BLOCK_SIZE);
+        for chunk in &mut chunks {
+            let output = self.advance();
+            for (bytes, word) in chunk.chunks_exact_mut(4).zip(output) {
+                let word = u32::from_le_bytes((&*bytes).try_into().unwrap()) ^ word;
+                bytes.copy_from_slice(&word.to_le_bytes());
+            }
+        }
+
+        // A partial final block is kept so the next call can continue it
+        let tail = chunks.into_remainder();
+        if !tail.is_empty() {
+            self.next_block();
+            xor_in_place(tail, &self.block[..tail.len()]);
+            self.offset = tail.len();
+        }
+        Ok(())
+    }
+
+    /// Moves to byte `offset` of the keystream, so the next byte processed is
+    /// XORed with keystream byte `offset`. Seeking backwards is allowed.
+    ///
+    /// Fails if `offset` is past the end of the keystream, 2^70 bytes.
+    pub fn seek(&mut self, offset: u128) -> Result<(), Salsa20Error> {
+        if offset > KEYSTREAM_LEN {
+            return Err(Salsa20Error::CounterOverflow);
+        }
+        self.seek_block(offset / BLOCK_SIZE as u128, (offset % BLOCK_SIZE as u128) as usize);
+        Ok(())
+    }
+
+    /// Byte offset of the next keystream byte to be used.
+    pub fn current_pos(&self) -> u128 {
+        let (block, byte) = self.block_and_byte();
+        block * BLOCK_SIZE as u128 + byte as u128
+    }
+
+    fn counter(&self) -> u64 {
+        self.state[8] as u64 | (self.state[9] as u64) << 32
+    }
+
+    fn set_counter(&mut self, counter: u64) {
+        self.state[8] = counter as u32;
+        self.state[9] = (counter >> 32) as u32;
+    }
+
+    /// Index of the block holding the next keystream byte, and that byte's
+    /// offset within the block. The block index reaches 2^64 at the end of
+    /// the keystream.
+    fn block_and_byte(&self) -> (u128, u8) {
+        let next = if self.exhausted { 1u128 << 64 } else { self.counter() as u128 };
+        let pos = next * BLOCK_SIZE as u128 - (BLOCK_SIZE - self.offset) as u128;
+        (pos / BLOCK_SIZE as u128, (pos % BLOCK_SIZE as u128) as u8)
+    }
+
+    /// Moves to byte `byte` of block `block`, where `block <= 2^64` and
+    /// `byte` is zero for block 2^64, the end of the keystream.
+    fn seek_block(&mut self, block: u128, byte: usize) {
+        self.exhausted = false;
+        self.offset = BLOCK_SIZE;
+        if block == 1 << 64 {
+            self.set_counter(u64::MAX);
+            self.exhausted = true;
+        } else {
+            self.set_counter(block as u64);
+            if byte > 0 {
+                self.next_block();
+                self.offset = byte;
+            }
+        }
+    }
+
+    /// Number of keystream bytes left before the counter runs out.
+    fn remaining(&self) -> u128 {
+        let buffered = (BLOCK_SIZE - self.offset) as u128;
+        if self.exhausted {
+            buffered
+        } else {
+            buffered + ((1u128 << 64) - self.counter() as u128) * BLOCK_SIZE as u128
+        }
+    }
+
+    /// Computes the keystream block for the current counter and advances it.
+    fn advance(&mut self) -> [u32; 16] {
+        // Salsa20 core function
+        let output = salsa20_core::<R>(&self.state);
+
+        // Increment counter
+        match self.counter().checked_add(1) {
+            Some(counter) => self.set_counter(counter),
+            None => self.exhausted = true,
+        }
+        output
+    }
+
+    /// Stores the next keystream block in `block` for partial use.
+    fn next_block(&mut self) {
+        let output = self.advance();
+        for (bytes, word) in self.block.chunks_exact_mut(4).zip(output) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        self.offset = 0;
+    }
+}
+
+/// XORs `keystream` into the front of `data`.
+fn xor_in_place(data: &mut [u8], keystream: &[u8]) {
+    for (byte, ks_byte) in data.iter_mut().zip(keystream) {
+        *byte ^= *ks_byte;
+    }
+}
+
+/// Builds a state from `key` and the 16 bytes placed in words 6-9.
+fn initial_state(key: &[u8; 32], input: &[u8; 16]) -> [u32; 16] {
+    let mut state = [0u32; 16];
+    let word = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+
+    // Load constants
+    state[0] = CONSTANTS[0];
+    state[5] = CONSTANTS[1];
+    state[10] = CONSTANTS[2];
+    state[15] = CONSTANTS[3];
+
+    // Load key
+    for i in 0..4 {
+        state[1 + i] = word(&key[i * 4..(i + 1) * 4]);
+        state[11 + i] = word(&key[16 + i * 4..16 + (i + 1) * 4]);
+    }
+
+    // Load nonce and counter
+    for i in 0..4 {
+        state[6 + i] = word(&input[i * 4..(i + 1) * 4]);
+    }
+    state
+}
+
+/// The Salsa20 quarterround on `y[a], y[b], y[c], y[d]`.
+fn quarter_round(y: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    y[b] ^= y[a].wrapping_add(y[d]).rotate_left(7);
+    y[c] ^= y[b].wrapping_add(y[a]).rotate_left(9);
+    y[d] ^= y[c].wrapping_add(y[b]).rotate_left(13);
+    y[a] ^= y[d].wrapping_add(y[c]).rotate_left(18);
+}
+
+/// Applies the quarterround to each column, starting from the diagonal.
+fn column_round(x: &mut [u32; 16]) {
+    quarter_round(x, 0, 4, 8, 12);
+    quarter_round(x, 5, 9, 13, 1);
+    quarter_round(x, 10, 14, 2, 6);
+    quarter_round(x, 15, 3, 7, 11);
+}
+
+/// Applies the quarterround to each row, starting from the diagonal.
+fn row_round(y: &mut [u32; 16]) {
+    quarter_round(y, 0, 1, 2, 3);
+    quarter_round(y, 5, 6, 7, 4);
+    quarter_round(y, 10, 11, 8, 9);
+    quarter_round(y, 15, 12, 13, 14);
+}
+
+fn double_round(x: &mut [u32; 16]) {
+    column_round(x);
+    row_round(x);
+}
+
+/// `R` Salsa20 rounds without the final addition.
+fn permute<const R: usize>(input: &[u32; 16]) -> [u32; 16] {
+    let mut x = *input;
+    for _ in 0..R / 2 {
+        double_round(&mut x);
+    }
+    x
+}
+
+/// The Salsa20 core: `R` rounds followed by adding the input back in.
+///
+/// `R` must be even, which is checked at compile time. Salsa20/8 (`R = 8`) is
+/// the mixing function of scrypt.
+///
+/// ```compile_fail
+/// task::salsa20_core::<7>(&[0u32; 16]);
+/// ```
+pub fn salsa20_core<const R: usize>(input: &[u32; 16]) -> [u32; 16] {
+    const { assert!(R.is_multiple_of(2), "Salsa20 runs whole double rounds, so R must be even") };
+    let mut x = permute::<R>(input);
+    for (word, input_word) in x.iter_mut().zip(input) {
+        *word = word.wrapping_add(*input_word);
+    }
+    x
+}
+
+/// Decodes a hex string for test vectors.
+#[cfg(test)]
+pub(crate) fn hex_bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Counts allocations made by the current thread, so tests running in
+    /// parallel do not disturb each other.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn quarter_round_words(words: [u32; 4]) -> [u32; 4] {
+        let mut y = [0u32; 16];
+        y[..4].copy_from_slice(&words);
+        quarter_round(&mut y, 0, 1, 2, 3);
+        y[..4].try_into().unwrap()
+    }
+
+    /// The Salsa20 hash function of the specification, on 64-byte sequences.
+    fn salsa20_hash(input: &[u8; 64]) -> [u8; 64] {
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(input.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        let mut output = [0u8; 64];
+        for (bytes, word) in output.chunks_exact_mut(4).zip(salsa20_core::<ROUNDS>(&words)) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        output
+    }
+
+    // Test vectors below are from Bernstein, "Salsa20 specification", sections 3-8
+    #[test]
+    fn test_quarter_round_vectors() {
+        assert_eq!(quarter_round_words([0, 0, 0, 0]), [0, 0, 0, 0]);
+        assert_eq!(quarter_round_words([1, 0, 0, 0]), [0x08008145, 0x00000080, 0x00010200, 0x20500000]);
+        assert_eq!(quarter_round_words([0, 1, 0, 0]), [0x88000100, 0x00000001, 0x00000200, 0x00402000]);
+        assert_eq!(
+            quarter_round_words([0xe7e8c006, 0xc4f9417d, 0x6479b4b2, 0x68c67137]),
+            [0xe876d72b, 0x9361dfd5, 0xf1460244, 0x948541a3]
+        );
+    }
+
+    #[test]
+    fn test_row_round_vector() {
+        let mut y = [1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0];
+        row_round(&mut y);
+        assert_eq!(
+            y,
+            [
+                0x08008145, 0x00000080, 0x00010200, 0x20500000,
+                0x20100001, 0x00048044, 0x00000080, 0x00010000,
+                0x00000001, 0x00002000, 0x80040000, 0x00000000,
+                0x00000001, 0x00000200, 0x00402000, 0x88000100,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_column_round_vector() {
+        let mut x = [1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0];
+        column_round(&mut x);
+        assert_eq!(
+            x,
+            [
+                0x10090288, 0x00000000, 0x00000000, 0x00000000,
+                0x00000101, 0x00000000, 0x00000000, 0x00000000,
+                0x00020401, 0x00000000, 0x00000000, 0x00000000,
+                0x40a04001, 0x00000000, 0x00000000, 0x00000000,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_double_round_vector() {
+        let mut x = [0u32; 16];
+        x[0] = 1;
+        double_round(&mut x);
+        assert_eq!(
+            x,
+            [
+                0x8186a22d, 0x0040a284, 0x82479210, 0x06929051,
+                0x08000090, 0x02402200, 0x00004000, 0x00800000,
+                0x00010200, 0x20400000, 0x08008104, 0x00000000,
+                0x20500000, 0xa0000040, 0x0008180a, 0x612a8020,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_salsa20_hash_vectors() {
+        assert_eq!(salsa20_hash(&[0u8; 64]), [0u8; 64]);
+
+        let input = [
+            211, 159, 13, 115, 76, 55, 82, 183, 3, 117, 222, 37, 191, 187, 234, 136,
+            49, 237, 179, 48, 1, 106, 178, 219, 175, 199, 166, 48, 86, 16, 179, 207,
+            31, 240, 32, 63, 15, 83, 93, 161, 116, 147, 48, 113, 238, 55, 204, 36,
+            79, 201, 235, 79, 3, 81, 156, 47, 203, 26, 244, 243, 88, 118, 104, 54,
+        ];
+        let expected = [
+            109, 42, 178, 168, 156, 240, 248, 238, 168, 196, 190, 203, 26, 110, 170, 154,
+            29, 29, 150, 26, 150, 30, 235, 249, 190, 163, 251, 48, 69, 144, 51, 57,
+            118, 40, 152, 157, 180, 57, 27, 94, 107, 42, 236, 35, 27, 111, 114, 114,
+            219, 236, 232, 135, 111, 155, 110, 18, 24, 232, 95, 158, 179, 19, 48, 202,
+        ];
+        assert_eq!(salsa20_hash(&input), expected);
+
+        let input = [
+            88, 118, 104, 54, 79, 201, 235, 79, 3, 81, 156, 47, 203, 26, 244, 243,
+            191, 187, 234, 136, 211, 159, 13, 115, 76, 55, 82, 183, 3, 117, 222, 37,
+            86, 16, 179, 207, 49, 237, 179, 48, 1, 106, 178, 219, 175, 199, 166, 48,
+            238, 55, 204, 36, 31, 240, 32, 63, 15, 83, 93, 161, 116, 147, 48, 113,
+        ];
+        let expected = [
+            179, 19, 48, 202, 219, 236, 232, 135, 111, 155, 110, 18, 24, 232, 95, 158,
+            26, 110, 170, 154, 109, 42, 178, 168, 156, 240, 248, 238, 168, 196, 190, 203,
+            69, 144, 51, 57, 29, 29, 150, 26, 150, 30, 235, 249, 190, 163, 251, 48,
+            27, 111, 114, 114, 118, 40, 152, 157, 180, 57, 27, 94, 107, 42, 236, 35,
+        ];
+        assert_eq!(salsa20_hash(&input), expected);
+    }
+
+    // eSTREAM Salsa20 256-bit test vectors, set 1, vector 0
+    #[test]
+    fn test_estream_set1_vector0() {
+        let mut key = [0u8; 32];
+        key[0] = 0x80;
+        let mut stream = [0u8; 512];
+        Salsa20::<20>::new(&key, &[0u8; 8]).encrypt(&mut stream);
+
+        assert_eq!(
+            stream[..64],
+            hex_bytes(
+                "e3be8fdd8beca2e3ea8ef9475b29a6e7003951e1097a5c38d23b7a5fad9f6844\
+                 b22c97559e2723c7cbbd3fe4fc8d9a0744652a83e72a9c461876af4d7ef1a117"
+            )[..]
+        );
+        assert_eq!(
+            stream[448..],
+            hex_bytes(
+                "696afcfd0cddcc83c7e77f11a649d79acdc3354e9635ff137e929933a0bd6f53\
+                 77efa105a3a4266b7c0d089d08f1e855cc32b15b93784a36e56a76cc64bc8477"
+            )[..]
+        );
+    }
+
+    // eSTREAM set 1, vector 0 for the reduced-round profiles, cross-checked
+    // with the RustCrypto salsa20 crate
+    #[test]
+    fn test_reduced_round_vectors() {
+        let mut key = [0u8; 32];
+        key[0] = 0x80;
+
+        let mut stream = [0u8; 512];
+        Salsa20::<8>::new(&key, &[0u8; 8]).encrypt(&mut stream);
+        assert_eq!(
+            stream[..64],
+            hex_bytes(
+                "b1f599e9b0d96df436ae31f5ef589565b92d245db5a1d4c7a78e5e8d0146f8a4\
+                 9d326c1a3bf50c052c9c8f114dc74972c4469591e31c9ed11927aa9871f38583"
+            )[..]
+        );
+        assert_eq!(
+            stream[448..],
+            hex_bytes(
+                "53bf865c66a344cfcd19177476a05aca5851cc45224b196abf3206d899e7fe3b\
+                 13b3f028fa849b5564561a9181ea69e512bc34da29180cdf6811e40a9a06a8d1"
+            )[..]
+        );
+
+        let mut stream = [0u8; 512];
+        Salsa20::<12>::new(&key, &[0u8; 8]).encrypt(&mut stream);
+        assert_eq!(
+            stream[..64],
+            hex_bytes(
+                "afe411ed1c4e07e4d0cde3b33e31ec190fa4cc796a58bafb848ead8d07d02cd2\
+                 d4b6f9f30cb0b57007e3733895cc8d1060107975acaeeb689b6cf614ab64a3d6"
+            )[..]
+        );
+        assert_eq!(
+            stream[448..],
+            hex_bytes(
+                "87a5191ec2e3c9049fa524cd8673e0677c77adcf8ab5328fd828c4acb3eccca5\
+                 49adeda04872518ecdf874adcb2420c7bd1ccfe561b074080224fa7176f0cb5f"
+            )[..]
+        );
+    }
+
+    #[test]
+    fn test_default_is_twenty_rounds() {
+        let mut default: Salsa20 = Salsa20::new(&[4u8; 32], &[5u8; 8]);
+        let mut full = Salsa20::<20>::new(&[4u8; 32], &[5u8; 8]);
+        let mut a = [0u8; 100];
+        let mut b = [0u8; 100];
+        default.encrypt(&mut a);
+        full.encrypt(&mut b);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_counter_high_word_is_used() {
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8 + 1);
+        let mut salsa = Salsa20::<20>::new(&key, &[3, 1, 4, 1, 5, 9, 2, 6]);
+        salsa.set_counter((1 << 32) + 7);
+
+        let mut block = [0u8; 64];
+        salsa.encrypt(&mut block);
+        // Reference output from the RustCrypto salsa20 crate
+        assert_eq!(
+            block[..],
+            hex_bytes(
+                "034f1a0587fd34b4da0c417209305ee5b11363c10e2909a8122dee2a01176c82\
+                 3b11b2d0a22e0f5469e79072520f5e0e4bbcfccfa45f0a0042591e60979c9cd2"
+            )[..]
+        );
+    }
+
+    #[test]
+    fn test_counter_overflow_is_detected() {
+        let mut salsa = Salsa20::<20>::new(&[1u8; 32], &[2u8; 8]);
+        salsa.set_counter(u64::MAX);
+
+        let mut too_long = [0u8; 65];
+        assert_eq!(salsa.try_encrypt(&mut too_long), Err(Salsa20Error::CounterOverflow));
+        assert_eq!(too_long, [0u8; 65], "nothing is encrypted on overflow");
+
+        // The final block is still usable, byte by byte
+        let mut last = [0u8; 64];
+        salsa.try_encrypt(&mut last[..10]).unwrap();
+        salsa.try_encrypt(&mut last[10..]).unwrap();
+        assert_ne!(last, [0u8; 64]);
+        assert_eq!(salsa.try_encrypt(&mut [0u8; 1]), Err(Salsa20Error::CounterOverflow));
+        salsa.try_encrypt(&mut []).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "block counter overflow")]
+    fn test_encrypt_panics_on_overflow() {
+        let mut salsa = Salsa20::<20>::new(&[1u8; 32], &[2u8; 8]);
+        salsa.set_counter(u64::MAX);
+        salsa.encrypt(&mut [0u8; 65]);
+    }
+
+    #[test]
+    fn test_consecutive_calls_continue_keystream() {
+        let key: [u8; 32] = rand::thread_rng().gen();
+        let nonce: [u8; 8] = rand::thread_rng().gen();
+        let message: Vec<u8> = (0..300).map(|_| rand::thread_rng().gen()).collect();
+
+        let mut whole = message.clone();
+        Salsa20::<20>::new(&key, &nonce).encrypt(&mut whole);
+
+        // Split points inside a block, on a block boundary and across several blocks
+        for split in [0, 1, 63, 64, 65, 200, 300] {
+            let (a, b) = message.split_at(split);
+            let mut a = a.to_vec();
+            let mut b = b.to_vec();
+            let mut salsa = Salsa20::<20>::new(&key, &nonce);
+            salsa.encrypt(&mut a);
+            salsa.encrypt(&mut b);
+            a.extend_from_slice(&b);
+            assert_eq!(a, whole, "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn test_encrypt_does_not_allocate() {
+        let mut salsa: Salsa20 = Salsa20::new(&[1u8; 32], &[2u8; 8]);
+        let mut buffer = vec![0u8; 10_000];
+
+        let before = ALLOCATIONS.with(Cell::get);
+        salsa.encrypt(&mut buffer[..3]);
+        salsa.encrypt(&mut buffer[3..9_000]);
+        salsa.encrypt(&mut buffer[9_000..]);
+        assert_eq!(ALLOCATIONS.with(Cell::get), before);
+    }
+
+    #[test]
+    fn test_seek_matches_slice_of_full_keystream() {
+        let mut rng = rand::thread_rng();
+        let key: [u8; 32] = rng.gen();
+        let nonce: [u8; 8] = rng.gen();
+        let mut full = vec![0u8; 4096];
+        Salsa20::<20>::new(&key, &nonce).encrypt(&mut full);
+
+        // One instance seeks back and forth, including within its buffered block
+        let mut salsa: Salsa20 = Salsa20::new(&key, &nonce);
+        for _ in 0..200 {
+            let start = rng.gen_range(0..full.len());
+            let end = rng.gen_range(start..=full.len());
+            salsa.seek(start as u128).unwrap();
+            assert_eq!(salsa.current_pos(), start as u128);
+
+            let mut range = vec![0u8; end - start];
+            salsa.encrypt(&mut range);
+            assert_eq!(range, full[start..end], "range {}..{}", start, end);
+            assert_eq!(salsa.current_pos(), end as u128);
+        }
+    }
+
+    #[test]
+    fn test_seek_uses_high_counter_word() {
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8 + 1);
+        let nonce = [3, 1, 4, 1, 5, 9, 2, 6];
+        let mut direct = Salsa20::<20>::new(&key, &nonce);
+        direct.set_counter((1 << 32) + 7);
+        let mut expected = [0u8; 64];
+        direct.encrypt(&mut expected);
+
+        let mut salsa = Salsa20::<20>::new(&key, &nonce);
+        salsa.seek(((1u128 << 32) + 7) * 64 + 10).unwrap();
+        let mut tail = [0u8; 54];
+        salsa.encrypt(&mut tail);
+        assert_eq!(tail, expected[10..]);
+    }
+
+    #[test]
+    fn test_seek_to_end_of_keystream() {
+        let mut salsa: Salsa20 = Salsa20::new(&[1u8; 32], &[2u8; 8]);
+        assert_eq!(salsa.seek(KEYSTREAM_LEN + 1), Err(Salsa20Error::CounterOverflow));
+
+        salsa.seek(KEYSTREAM_LEN - 3).unwrap();
+        assert_eq!(salsa.try_encrypt(&mut [0u8; 4]), Err(Salsa20Error::CounterOverflow));
+        salsa.encrypt(&mut [0u8; 3]);
+        assert_eq!(salsa.current_pos(), KEYSTREAM_LEN);
+
+        salsa.seek(KEYSTREAM_LEN).unwrap();
+        assert_eq!(salsa.try_encrypt(&mut [0u8; 1]), Err(Salsa20Error::CounterOverflow));
+
+        // Seeking back after reaching the end makes the keystream usable again
+        salsa.seek(0).unwrap();
+        salsa.encrypt(&mut [0u8; 100]);
+        assert_eq!(salsa.current_pos(), 100);
+    }
+
+    #[test]
+    fn test_reused_instance_does_not_repeat_keystream() {
+        let mut salsa = Salsa20::<20>::new(&[7u8; 32], &[9u8; 8]);
+        let mut first = [0u8; 64];
+        let mut second = [0u8; 64];
+        salsa.encrypt(&mut first);
+        salsa.encrypt(&mut second);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_salsa20_encryption_decryption() {
+        let key: [u8; 32] = rand::thread_rng().gen();
+        let nonce: [u8; 8] = rand::thread_rng().gen();
+        let mut salsa = Salsa20::<20>::new(&key, &nonce);
+
+        let mut plaintext = b"Test encryption message".to_vec();
+        let original_plaintext = plaintext.clone();
+
+        // Encrypt the plaintext
+        salsa.encrypt(&mut plaintext);
+        assert_ne!(plaintext, original_plaintext, "Ciphertext should not be equal to plaintext");
+
+        // Decrypt the ciphertext
+        let mut salsa_decrypt = Salsa20::<20>::new(&key, &nonce);
+        salsa_decrypt.encrypt(&mut plaintext);
+        assert_eq!(plaintext, original_plaintext, "Decrypted text should match original plaintext");
+    }
+
+    #[test]
+    fn test_empty_message() {
+        let key: [u8; 32] = rand::thread_rng().gen();
+        let nonce: [u8; 8] = rand::thread_rng().gen();
+        let mut salsa = Salsa20::<20>::new(&key, &nonce);
+
+        let mut empty_message: Vec<u8> = vec![];
+        let original_message = empty_message.clone();
+
+        salsa.encrypt(&mut empty_message);
+        assert_eq!(empty_message, original_message, "Empty message should remain unchanged after encryption/decryption");
+    }
+
+    #[test]
+    fn test_encrypt_large_message() {
+        let key: [u8; 32] = rand::thread_rng().gen();
+        let nonce: [u8; 8] = rand::thread_rng().gen();
+        let mut salsa = Salsa20::<20>::new(&key, &nonce);
+
+        let mut large_message = vec![0u8; 1024]; // 1 KB message
+        let original_message = large_message.clone();
+
+        salsa.encrypt(&mut large_message);
+        assert_ne!(large_message, original_message, "Ciphertext should not match plaintext for large message");
+
+        let mut salsa_decrypt = Salsa20::<20>::new(&key, &nonce);
+        salsa_decrypt.encrypt(&mut large_message);
+        assert_eq!(large_message, original_message, "Decrypted large message should match original plaintext");
+    }
+}
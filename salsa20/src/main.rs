@@ -1,167 +1,284 @@
 // src/main.rs
 
-use rand::Rng;
-
-const QUARTER_ROUNDS: usize = 20;
-
-pub struct Salsa20 {
-    state: [u32; 16],
+use rand::RngCore;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use task::nonce_log::NonceLog;
+use task::{Salsa20, XSalsa20};
+
+const USAGE: &str = "\
+Usage:
+  salsa20 encrypt (--key HEX | --key-file PATH) [--nonce HEX] [--nonce-log PATH] [-i INPUT] [-o OUTPUT]
+  salsa20 decrypt (--key HEX | --key-file PATH) --nonce HEX [-i INPUT] [-o OUTPUT]
+  salsa20 keygen OUTPUT
+
+Input defaults to stdin and output to stdout. Keys are 32 bytes, given as 64
+hex digits or in a file holding either the raw bytes or the hex digits. A
+16-digit nonce selects Salsa20 and a 48-digit nonce XSalsa20; without --nonce,
+encrypt picks a random XSalsa20 nonce and prints it to stderr.
+
+Encryption refuses a nonce already recorded for the same key in the nonce log,
+$HOME/.salsa20-nonces unless --nonce-log is given.";
+
+/// Bytes read, encrypted and written at a time; a multiple of the block size.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+enum Command {
+    Encrypt,
+    Decrypt,
 }
 
-impl Salsa20 {
-    pub fn new(key: &[u8; 32], nonce: &[u8; 8]) -> Salsa20 {
-        let mut state = [
-            0u32, 0u32, 0u32, 0u32,
-            0u32, 0u32, 0u32, 0u32,
-            0u32, 0u32, 0u32, 0u32,
-            0u32, 0u32, 0u32, 0u32,
-        ];
+enum KeySource {
+    Hex(String),
+    File(PathBuf),
+}
 
-        // Load constants
-        state[0] = 0x61707865; // "expa"
-        state[1] = 0x3320646e; // "nd 3"
-        state[2] = 0x79622d32; // "2-by"
-        state[3] = 0x6b206574; // "te k"
+struct Options {
+    command: Command,
+    key_source: KeySource,
+    nonce: Option<String>,
+    nonce_log: Option<PathBuf>,
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+}
 
-        // Load key
-        for i in 0..8 {
-            state[4 + i] = u32::from_le_bytes(key[i * 4..(i + 1) * 4].try_into().unwrap());
+fn parse_options(command: Command, args: &[String]) -> Result<Options, String> {
+    let mut key_source = None;
+    let mut nonce = None;
+    let mut nonce_log = None;
+    let mut input = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--key" => key_source = Some(KeySource::Hex(value()?)),
+            "--key-file" | "-k" => key_source = Some(KeySource::File(value()?.into())),
+            "--nonce" | "-n" => nonce = Some(value()?),
+            "--nonce-log" => nonce_log = Some(value()?.into()),
+            "--input" | "-i" => input = Some(value()?.into()),
+            "--output" | "-o" => output = Some(value()?.into()),
+            _ => return Err(format!("unknown argument: {}", arg)),
         }
-
-        // Load counter
-        state[12] = 0; // Counter
-        state[13] = u32::from_le_bytes(nonce[0..4].try_into().unwrap());
-        state[14] = u32::from_le_bytes(nonce[4..8].try_into().unwrap()); 
-
-        Salsa20 { state }
     }
 
-    pub fn encrypt(&mut self, input: &mut [u8]) {
-        let keystream = self.generate_keystream(input.len());
-
-        for (byte, ks_byte) in input.iter_mut().zip(keystream.iter()) {
-            *byte ^= *ks_byte;
-        }
+    if matches!(command, Command::Decrypt) && nonce.is_none() {
+        return Err(String::from("decrypt needs --nonce"));
     }
+    Ok(Options {
+        command,
+        key_source: key_source.ok_or("either --key or --key-file is required")?,
+        nonce,
+        nonce_log,
+        input,
+        output,
+    })
+}
 
-    fn generate_keystream(&mut self, length: usize) -> Vec<u8> {
-        let mut keystream = Vec::with_capacity(length);
-        let mut state = self.state;
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(String::from("invalid hex string"));
+    }
+    Ok((0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect())
+}
 
-        for _ in 0..(length / 64) + 1 {
-            // Copy state for output
-            let mut output = state;
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-            // Salsa20 core function
-            for _ in 0..QUARTER_ROUNDS {
-                self.quarter_round(&mut output);
+fn read_key(source: &KeySource) -> Result<[u8; 32], String> {
+    let bytes = match source {
+        KeySource::Hex(hex) => parse_hex(hex).map_err(|e| format!("--key: {}", e))?,
+        KeySource::File(path) => {
+            let contents = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            // Hex is tried first, so 32 hex digits are a short key rather than raw bytes
+            let hex = std::str::from_utf8(&contents)
+                .ok()
+                .map(str::trim)
+                .filter(|hex| !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()));
+            match hex {
+                Some(hex) => parse_hex(hex).map_err(|e| format!("{}: {}", path.display(), e))?,
+                None if contents.len() == 32 => contents,
+                None => return Err(format!("{}: expected 32 raw bytes or 64 hex digits", path.display())),
             }
+        }
+    };
+    bytes.try_into().map_err(|_| String::from("key must be exactly 32 bytes"))
+}
 
-            // Output generation
-            for i in 0..16 {
-                let keystream_word = output[i].to_le_bytes();
-                keystream.extend_from_slice(&keystream_word);
-            }
+/// Salsa20 for 8-byte nonces, XSalsa20 for 24-byte nonces.
+enum Cipher {
+    Salsa20(Salsa20),
+    XSalsa20(XSalsa20),
+}
 
-            // Increment counter
-            state[12] = state[12].wrapping_add(1);
+impl Cipher {
+    fn new(key: &[u8; 32], nonce: &[u8]) -> Result<Cipher, String> {
+        match nonce.len() {
+            8 => Ok(Cipher::Salsa20(Salsa20::new(key, nonce.try_into().unwrap()))),
+            24 => Ok(Cipher::XSalsa20(XSalsa20::new(key, nonce.try_into().unwrap()))),
+            _ => Err(String::from("nonce must be 8 bytes (Salsa20) or 24 bytes (XSalsa20)")),
         }
-
-        keystream.truncate(length);
-        keystream
     }
 
-    fn quarter_round(&self, state: &mut [u32; 16]) {
-        // Perform a quarter round on the state
-        let x = state;
+    fn apply(&mut self, data: &mut [u8]) -> Result<(), String> {
+        match self {
+            Cipher::Salsa20(cipher) => cipher.try_encrypt(data),
+            Cipher::XSalsa20(cipher) => cipher.try_encrypt(data),
+        }
+        .map_err(|e| e.to_string())
+    }
+}
 
-        x[0] = x[0].wrapping_add(x[4]);
-        x[12] = x[12] ^ x[0];
-        x[12] = x[12].rotate_left(16);
+fn default_nonce_log() -> Result<PathBuf, String> {
+    env::var_os("HOME")
+        .map(|home| Path::new(&home).join(".salsa20-nonces"))
+        .ok_or_else(|| String::from("HOME is not set; pass --nonce-log"))
+}
 
-        x[8] = x[8].wrapping_add(x[12]);
-        x[4] = x[4] ^ x[8];
-        x[4] = x[4].rotate_left(12);
+fn process<R: Read, W: Write>(cipher: &mut Cipher, mut input: R, mut output: W) -> Result<(), String> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let len = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(format!("read error: {}", err)),
+        };
+        cipher.apply(&mut buffer[..len])?;
+        output.write_all(&buffer[..len]).map_err(|e| format!("write error: {}", e))?;
+    }
+    output.flush().map_err(|e| format!("write error: {}", e))
+}
 
-        x[0] = x[0].wrapping_add(x[4]);
-        x[12] = x[12] ^ x[0];
-        x[12] = x[12].rotate_left(8);
+fn run(options: Options) -> Result<(), String> {
+    let key = read_key(&options.key_source)?;
+    let nonce = match &options.nonce {
+        Some(hex) => parse_hex(hex).map_err(|e| format!("--nonce: {}", e))?,
+        None => {
+            let mut nonce = vec![0u8; 24];
+            rand::thread_rng().fill_bytes(&mut nonce);
+            eprintln!("nonce: {}", to_hex(&nonce));
+            nonce
+        }
+    };
+    let mut cipher = Cipher::new(&key, &nonce)?;
+
+    // The nonce is logged before any ciphertext exists, so an interrupted run
+    // cannot leave a nonce that was used but not recorded
+    if let Command::Encrypt = options.command {
+        let log_path = match options.nonce_log {
+            Some(path) => path,
+            None => default_nonce_log()?,
+        };
+        NonceLog::new(&log_path).record(&key, &nonce).map_err(|e| format!("{}: {}", log_path.display(), e))?;
+    }
 
-        x[8] = x[8].wrapping_add(x[12]);
-        x[4] = x[4] ^ x[8];
-        x[4] = x[4].rotate_left(7);
+    let input: Box<dyn Read> = match &options.input {
+        Some(path) => Box::new(File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    match &options.output {
+        Some(path) => {
+            // Write next to the destination and only move it into place once
+            // all input has been processed
+            let partial = partial_path(path);
+            let out = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&partial)
+                .map_err(|e| format!("{}: {}", partial.display(), e))?;
+            match process(&mut cipher, input, BufWriter::new(out)) {
+                Ok(()) => fs::rename(&partial, path).map_err(|e| format!("{}: {}", path.display(), e)),
+                Err(err) => {
+                    let _ = fs::remove_file(&partial);
+                    Err(err)
+                }
+            }
+        }
+        None => process(&mut cipher, input, BufWriter::new(io::stdout().lock())),
     }
 }
 
-fn main() {
-    let key: [u8; 32] = rand::thread_rng().gen();
-    let nonce: [u8; 8] = rand::thread_rng().gen();
-
-    let mut salsa = Salsa20::new(&key, &nonce);
+/// `notes.txt` becomes `notes.txt.partial`. The file is created exclusively,
+/// so an existing file of that name is never overwritten or removed.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
 
-    let mut plaintext = b"Hello, Salsa20! This is a test message.".to_vec();
+fn keygen(path: &Path) -> io::Result<()> {
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
 
-    println!("Original plaintext: {:?}", String::from_utf8_lossy(&plaintext));
+    let mut open = fs::OpenOptions::new();
+    open.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        open.mode(0o600);
+    }
+    open.open(path)?.write_all(&key)
+}
 
-    salsa.encrypt(&mut plaintext);
-    println!("Encrypted text: {:?}", plaintext);
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    // To decrypt, create a new Salsa20 instance with the same key and nonce
-    let mut salsa_decrypt = Salsa20::new(&key, &nonce);
-    salsa_decrypt.encrypt(&mut plaintext);
-    println!("Decrypted text: {:?}", String::from_utf8_lossy(&plaintext));
+    let result = match args.first().map(String::as_str) {
+        Some("encrypt") => parse_options(Command::Encrypt, &args[1..]).map(run),
+        Some("decrypt") => parse_options(Command::Decrypt, &args[1..]).map(run),
+        Some("keygen") if args.len() == 2 => {
+            Ok(keygen(Path::new(&args[1])).map_err(|e| format!("{}: {}", args[1], e)))
+        }
+        _ => Err(String::from("missing or unknown command")),
+    };
+
+    match result {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(err)) => {
+            eprintln!("salsa20: {}", err);
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("salsa20: {}\n\n{}", err, USAGE);
+            ExitCode::from(2)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_salsa20_encryption_decryption() {
-        let key: [u8; 32] = rand::thread_rng().gen();
-        let nonce: [u8; 8] = rand::thread_rng().gen();
-        let mut salsa = Salsa20::new(&key, &nonce);
-
-        let mut plaintext = b"Test encryption message".to_vec();
-        let original_plaintext = plaintext.clone();
-
-        // Encrypt the plaintext
-        salsa.encrypt(&mut plaintext);
-        assert_ne!(plaintext, original_plaintext, "Ciphertext should not be equal to plaintext");
-
-        // Decrypt the ciphertext
-        let mut salsa_decrypt = Salsa20::new(&key, &nonce);
-        salsa_decrypt.encrypt(&mut plaintext);
-        assert_eq!(plaintext, original_plaintext, "Decrypted text should match original plaintext");
+    fn key_file(name: &str, contents: &[u8]) -> Result<[u8; 32], String> {
+        let path = env::temp_dir().join(format!("salsa20-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let key = read_key(&KeySource::File(path.clone()));
+        fs::remove_file(&path).unwrap();
+        key
     }
 
     #[test]
-    fn test_empty_message() {
-        let key: [u8; 32] = rand::thread_rng().gen();
-        let nonce: [u8; 8] = rand::thread_rng().gen();
-        let mut salsa = Salsa20::new(&key, &nonce);
-
-        let mut empty_message: Vec<u8> = vec![];
-        let original_message = empty_message.clone();
-
-        salsa.encrypt(&mut empty_message);
-        assert_eq!(empty_message, original_message, "Empty message should remain unchanged after encryption/decryption");
+    fn test_key_files() {
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8 * 7);
+        assert_eq!(key_file("raw", &key), Ok(key));
+        assert_eq!(key_file("hex", format!("{}\n", to_hex(&key)).as_bytes()), Ok(key));
+
+        // 32 hex digits are half a key, not 32 raw bytes
+        assert!(key_file("short-hex", to_hex(&key[..16]).as_bytes()).is_err());
+        assert!(key_file("long", &[0u8; 33]).is_err());
     }
 
     #[test]
-    fn test_encrypt_large_message() {
-        let key: [u8; 32] = rand::thread_rng().gen();
-        let nonce: [u8; 8] = rand::thread_rng().gen();
-        let mut salsa = Salsa20::new(&key, &nonce);
-
-        let mut large_message = vec![0u8; 1024]; // 1 KB message
-        let original_message = large_message.clone();
-
-        salsa.encrypt(&mut large_message);
-        assert_ne!(large_message, original_message, "Ciphertext should not match plaintext for large message");
-
-        let mut salsa_decrypt = Salsa20::new(&key, &nonce);
-        salsa_decrypt.encrypt(&mut large_message);
-        assert_eq!(large_message, original_message, "Decrypted large message should match original plaintext");
+    fn test_partial_path_appends_suffix() {
+        assert_eq!(partial_path(Path::new("out/notes.txt")), Path::new("out/notes.txt.partial"));
+        assert_eq!(partial_path(Path::new("notes")), Path::new("notes.partial"));
     }
 }
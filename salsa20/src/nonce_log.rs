@@ -0,0 +1,157 @@
+//! A local record of the nonces used with each key, to refuse nonce reuse.
+//!
+//! Each line of the log holds a key fingerprint and a nonce, both in hex. The
+//! fingerprint is a domain-separated SHA-256 of the key truncated to 16 bytes,
+//! so the log identifies keys without storing them. A nonce is recorded and
+//! synced to disk before anything is encrypted with it.
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Length of a key fingerprint in bytes.
+pub const FINGERPRINT_LEN: usize = 16;
+
+/// Errors returned by [`NonceLog::record`].
+#[derive(Debug)]
+pub enum NonceLogError {
+    /// The nonce was already used with this key.
+    Reused,
+    /// The log could not be read or written.
+    Io(io::Error),
+    /// A line of the log is not a fingerprint and nonce pair.
+    Corrupt(usize),
+}
+
+impl fmt::Display for NonceLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NonceLogError::Reused => write!(f, "nonce was already used with this key"),
+            NonceLogError::Io(err) => write!(f, "nonce log I/O error: {}", err),
+            NonceLogError::Corrupt(line) => write!(f, "nonce log is corrupt at line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for NonceLogError {}
+
+impl From<io::Error> for NonceLogError {
+    fn from(err: io::Error) -> NonceLogError {
+        NonceLogError::Io(err)
+    }
+}
+
+/// Identifies `key` in the log without revealing it.
+pub fn fingerprint(key: &[u8; 32]) -> [u8; FINGERPRINT_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"salsa20 nonce log v1");
+    hasher.update(key);
+    hasher.finalize()[..FINGERPRINT_LEN].try_into().unwrap()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A nonce log stored at a path; the file is created on first use.
+pub struct NonceLog {
+    path: PathBuf,
+}
+
+impl NonceLog {
+    /// Uses the log at `path`. Nothing is opened or created yet, so this
+    /// cannot fail; a missing file counts as an empty log, and [`NonceLog::record`]
+    /// creates it, reporting any I/O error then.
+    pub fn new(path: impl AsRef<Path>) -> NonceLog {
+        NonceLog { path: path.as_ref().to_path_buf() }
+    }
+
+    /// Reports whether `nonce` has been recorded for `key`.
+    pub fn contains(&self, key: &[u8; 32], nonce: &[u8]) -> Result<bool, NonceLogError> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+
+        let fingerprint = to_hex(&fingerprint(key));
+        let nonce = to_hex(nonce);
+        for (index, line) in contents.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(logged_key), Some(logged_nonce), None) => {
+                    if logged_key == fingerprint && logged_nonce == nonce {
+                        return Ok(true);
+                    }
+                }
+                (None, _, _) => {}
+                _ => return Err(NonceLogError::Corrupt(index + 1)),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Records `nonce` for `key`, failing with [`NonceLogError::Reused`] if it
+    /// was recorded before.
+    ///
+    /// The check and the append are not atomic across processes sharing one log.
+    pub fn record(&self, key: &[u8; 32], nonce: &[u8]) -> Result<(), NonceLogError> {
+        if self.contains(key, nonce)? {
+            return Err(NonceLogError::Reused);
+        }
+
+        let mut open = OpenOptions::new();
+        open.append(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            open.mode(0o600);
+        }
+        let mut file = open.open(&self.path)?;
+        writeln!(file, "{} {}", to_hex(&fingerprint(key)), to_hex(nonce))?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    fn temp_log_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}-{}", name, std::process::id(), rand::thread_rng().next_u64()))
+    }
+
+    #[test]
+    fn test_reuse_is_refused_per_key() {
+        let path = temp_log_path("nonce-log");
+        let log = NonceLog::new(&path);
+        let key = [1u8; 32];
+        let other_key = [2u8; 32];
+
+        log.record(&key, &[7u8; 24]).unwrap();
+        log.record(&key, &[8u8; 24]).unwrap();
+        log.record(&other_key, &[7u8; 24]).unwrap();
+        assert!(matches!(log.record(&key, &[7u8; 24]), Err(NonceLogError::Reused)));
+
+        // The record survives reopening the log
+        let reopened = NonceLog::new(&path);
+        assert!(reopened.contains(&key, &[8u8; 24]).unwrap());
+        assert!(!reopened.contains(&other_key, &[8u8; 24]).unwrap());
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains(&to_hex(&key)), "keys must not be written to the log");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_log_is_reported() {
+        let path = temp_log_path("nonce-log-corrupt");
+        fs::write(&path, "00ff 1122\nnot-a-record\n").unwrap();
+        assert!(matches!(NonceLog::new(&path).record(&[1u8; 32], &[0u8; 8]), Err(NonceLogError::Corrupt(2))));
+        fs::remove_file(&path).unwrap();
+    }
+}
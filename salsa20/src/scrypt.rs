@@ -0,0 +1,276 @@
+//! The scrypt memory-hard password-based key derivation function (RFC 7914),
+//! built on this crate's Salsa20/8 core.
+//!
+//! PBKDF2-HMAC-SHA256 expands the password into `p` blocks of `128 * r`
+//! bytes, ROMix makes each block depend on a table of `N` earlier states,
+//! and a final PBKDF2 pass turns the mixed blocks into the derived key.
+
+use crate::salsa20_core;
+use alloc::vec;
+use core::fmt;
+use sha2::Sha256;
+
+/// Errors returned by [`Params::new`] and [`scrypt`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScryptError {
+    /// `log_n` must be between 1 and 63 and below `16 * r` (RFC 7914 section 2).
+    InvalidCost,
+    /// `r` and `p` must be non-zero with `r * p < 2^30`.
+    InvalidBlockParams,
+    /// `128 * r * N` bytes cannot be allocated on this platform.
+    MemoryTooLarge,
+    /// The output must be between 1 and `(2^32 - 1) * 32` bytes.
+    InvalidOutputLen,
+}
+
+impl fmt::Display for ScryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScryptError::InvalidCost => write!(f, "scrypt log_n must be between 1 and 63 and less than 16 * r"),
+            ScryptError::InvalidBlockParams => write!(f, "scrypt r and p must be non-zero with r * p < 2^30"),
+            ScryptError::MemoryTooLarge => write!(f, "scrypt parameters need more memory than is addressable"),
+            ScryptError::InvalidOutputLen => write!(f, "invalid scrypt output length"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ScryptError {}
+
+/// The scrypt cost parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl Params {
+    /// `N = 2^log_n` is the CPU/memory cost, `r` the block size and `p` the
+    /// parallelization. Memory use is about `128 * r * N` bytes.
+    ///
+    /// As RFC 7914 requires, `log_n` must be below `16 * r`, so `r = 1` allows
+    /// at most N = 2^15.
+    pub fn new(log_n: u8, r: u32, p: u32) -> Result<Params, ScryptError> {
+        if log_n == 0 || log_n >= 64 {
+            return Err(ScryptError::InvalidCost);
+        }
+        if r == 0 || p == 0 || (r as u64) * (p as u64) >= 1 << 30 {
+            return Err(ScryptError::InvalidBlockParams);
+        }
+        // RFC 7914 requires N < 2^(128 * r / 8)
+        if log_n as u64 >= 16 * r as u64 {
+            return Err(ScryptError::InvalidCost);
+        }
+        let block_len = block_len(r)?;
+        block_len.checked_mul(p as usize).ok_or(ScryptError::MemoryTooLarge)?;
+        1usize
+            .checked_shl(log_n as u32)
+            .and_then(|n| n.checked_mul(block_len))
+            .ok_or(ScryptError::MemoryTooLarge)?;
+        Ok(Params { log_n, r, p })
+    }
+
+    /// Interactive-login parameters from the scrypt paper: N = 2^15, r = 8, p = 1.
+    pub fn recommended() -> Params {
+        Params { log_n: 15, r: 8, p: 1 }
+    }
+
+    /// The base-2 logarithm of the cost `N`.
+    pub fn log_n(&self) -> u8 {
+        self.log_n
+    }
+
+    /// The block size `r`; each block is `128 * r` bytes.
+    pub fn r(&self) -> u32 {
+        self.r
+    }
+
+    /// The parallelization `p`, the number of blocks mixed independently.
+    pub fn p(&self) -> u32 {
+        self.p
+    }
+}
+
+/// Bytes in one scrypt block, `128 * r`.
+fn block_len(r: u32) -> Result<usize, ScryptError> {
+    128usize.checked_mul(r as usize).ok_or(ScryptError::MemoryTooLarge)
+}
+
+/// Derives `output.len()` bytes from `password` and `salt`.
+pub fn scrypt(password: &[u8], salt: &[u8], params: &Params, output: &mut [u8]) -> Result<(), ScryptError> {
+    if output.is_empty() || output.len() as u64 > (u32::MAX as u64) * 32 {
+        return Err(ScryptError::InvalidOutputLen);
+    }
+
+    let n = 1usize << params.log_n;
+    let block_len = block_len(params.r)?;
+    let blocks_len = block_len.checked_mul(params.p as usize).ok_or(ScryptError::MemoryTooLarge)?;
+
+    let mut blocks = vec![0u8; blocks_len];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, 1, &mut blocks);
+
+    let mut words = vec![0u32; block_len / 4];
+    let mut table = vec![0u32; n * block_len / 4];
+    let mut scratch = vec![0u32; block_len / 4];
+    for block in blocks.chunks_exact_mut(block_len) {
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        ro_mix(&mut words, &mut table, &mut scratch, n);
+        for (bytes, word) in block.chunks_exact_mut(4).zip(&words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, &blocks, 1, output);
+    Ok(())
+}
+
+/// BlockMix with Salsa20/8: mixes the `2 * r` 64-byte blocks of `input` into
+/// `output`, writing even-indexed results first and odd-indexed ones after.
+fn block_mix(input: &[u32], output: &mut [u32]) {
+    let blocks = input.len() / 16;
+    let mut x: [u32; 16] = input[input.len() - 16..].try_into().unwrap();
+
+    for (i, block) in input.chunks_exact(16).enumerate() {
+        for (word, input_word) in x.iter_mut().zip(block) {
+            *word ^= *input_word;
+        }
+        x = salsa20_core::<8>(&x);
+
+        let position = if i % 2 == 0 { i / 2 } else { blocks / 2 + i / 2 };
+        output[position * 16..(position + 1) * 16].copy_from_slice(&x);
+    }
+}
+
+/// ROMix: fills `table` with `n` successive BlockMix states of `block`, then
+/// mixes in `n` entries chosen by the data itself.
+fn ro_mix(block: &mut [u32], table: &mut [u32], scratch: &mut [u32], n: usize) {
+    let len = block.len();
+
+    for entry in table.chunks_exact_mut(len) {
+        entry.copy_from_slice(block);
+        block_mix(entry, block);
+    }
+
+    for _ in 0..n {
+        let j = integerify(block) & (n - 1);
+        for (word, entry_word) in block.iter_mut().zip(&table[j * len..(j + 1) * len]) {
+            *word ^= *entry_word;
+        }
+        block_mix(block, scratch);
+        block.copy_from_slice(scratch);
+    }
+}
+
+/// Reads the first 64 bits of the last 64-byte block as a little-endian integer.
+fn integerify(block: &[u32]) -> usize {
+    let last = block.len() - 16;
+    (block[last] as u64 | (block[last + 1] as u64) << 32) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_bytes;
+
+    fn words(bytes: &[u8]) -> Vec<u32> {
+        bytes.chunks_exact(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).collect()
+    }
+
+    // RFC 7914 section 8
+    #[test]
+    fn test_salsa20_8_core_vector() {
+        let input = words(&hex_bytes(
+            "7e879a214f3ec9867ca940e641718f26baee555b8c61c1b50df846116dcd3b1d\
+             ee24f319df9b3d8514121e4b5ac5aa3276021d2909c74829edebc68db8b8c25e",
+        ));
+        let expected = words(&hex_bytes(
+            "a41f859c6608cc993b81cacb020cef05044b2181a2fd337dfd7b1c6396682f29\
+             b4393168e3c9e6bcfe6bc5b7a06d96bae424cc102c91745c24ad673dc7618f81",
+        ));
+        assert_eq!(salsa20_core::<8>(&input[..].try_into().unwrap())[..], expected[..]);
+    }
+
+    fn check(password: &[u8], salt: &[u8], log_n: u8, r: u32, p: u32, expected: &str) {
+        let expected = hex_bytes(expected);
+        let mut output = vec![0u8; expected.len()];
+        scrypt(password, salt, &Params::new(log_n, r, p).unwrap(), &mut output).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    // RFC 7914 section 12
+    #[test]
+    fn test_rfc7914_empty_password() {
+        check(
+            b"",
+            b"",
+            4,
+            1,
+            1,
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+             fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906",
+        );
+    }
+
+    #[test]
+    fn test_rfc7914_password_nacl() {
+        check(
+            b"password",
+            b"NaCl",
+            10,
+            8,
+            16,
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+             2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640",
+        );
+    }
+
+    #[test]
+    fn test_rfc7914_sodium_chloride() {
+        check(
+            b"pleaseletmein",
+            b"SodiumChloride",
+            14,
+            8,
+            1,
+            "7023bdcb3afd7348461c06cd81fd38ebfda8fbba904f8e3ea9b543f6545da1f2\
+             d5432955613f0fcf62d49705242a9af9e61e85dc0d651e40dfcf017b45575887",
+        );
+    }
+
+    // The last RFC vector needs 1 GiB of memory
+    #[test]
+    #[ignore]
+    fn test_rfc7914_large_cost() {
+        check(
+            b"pleaseletmein",
+            b"SodiumChloride",
+            20,
+            8,
+            1,
+            "2101cb9b6a511aaeaddbbe09cf70f881ec568d574a2ffd4dabe5ee9820adaa47\
+             8e56fd8f4ba5d09ffa1c6d927c40f4c337304049e8a952fbcbf45c6fa77a41a4",
+        );
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        assert_eq!(Params::new(0, 8, 1), Err(ScryptError::InvalidCost));
+        assert_eq!(Params::new(64, 8, 1), Err(ScryptError::InvalidCost));
+        assert_eq!(Params::new(20, 1, 1), Err(ScryptError::InvalidCost));
+        assert_eq!(Params::new(16, 1, 1), Err(ScryptError::InvalidCost));
+        assert!(Params::new(15, 1, 1).is_ok());
+        assert_eq!(Params::new(10, 0, 1), Err(ScryptError::InvalidBlockParams));
+        assert_eq!(Params::new(10, 8, 0), Err(ScryptError::InvalidBlockParams));
+        assert_eq!(Params::new(10, 1 << 15, 1 << 15), Err(ScryptError::InvalidBlockParams));
+        assert_eq!(Params::new(63, 8, 1), Err(ScryptError::MemoryTooLarge));
+        // 128 * r alone overflows a 32-bit usize
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(Params::new(1, 1 << 25, 1), Err(ScryptError::MemoryTooLarge));
+
+        let params = Params::new(4, 1, 1).unwrap();
+        assert_eq!(scrypt(b"pw", b"salt", &params, &mut []), Err(ScryptError::InvalidOutputLen));
+    }
+}
@@ -0,0 +1,145 @@
+//! NaCl `crypto_secretbox`: XSalsa20 encryption with a Poly1305 tag.
+//!
+//! The first 32 bytes of the XSalsa20 keystream become a one-time Poly1305
+//! key, the message is encrypted with the keystream from byte 32 on, and the
+//! tag covers the ciphertext. Sealed boxes use libsodium's combined layout
+//! (`crypto_secretbox_easy`): the 16-byte tag followed by the ciphertext.
+
+use crate::XSalsa20;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use poly1305::universal_hash::KeyInit;
+use poly1305::Poly1305;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+/// Length of a secretbox key in bytes.
+pub const KEY_LEN: usize = 32;
+/// Length of a secretbox nonce in bytes, the XSalsa20 nonce.
+pub const NONCE_LEN: usize = 24;
+/// Length of the Poly1305 tag that leads every sealed box.
+pub const TAG_LEN: usize = 16;
+
+/// Errors returned by [`open`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretBoxError {
+    /// The input is shorter than a tag.
+    TooShort,
+    /// The tag does not match; the box was forged, corrupted or uses another key or nonce.
+    Authentication,
+}
+
+impl fmt::Display for SecretBoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretBoxError::TooShort => write!(f, "secretbox is shorter than its tag"),
+            SecretBoxError::Authentication => write!(f, "secretbox failed authentication"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SecretBoxError {}
+
+/// Sets up the cipher for `nonce` and takes the Poly1305 key from its first 32 bytes.
+fn cipher_and_mac(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN]) -> (XSalsa20, Poly1305) {
+    let mut cipher = XSalsa20::new(key, nonce);
+    let mut mac_key = [0u8; 32];
+    cipher.encrypt(&mut mac_key);
+    let mac = Poly1305::new(&mac_key.into());
+    mac_key.zeroize();
+    (cipher, mac)
+}
+
+/// Encrypts and authenticates `plaintext`, returning tag || ciphertext.
+///
+/// A nonce must never be reused with the same key; 24 random bytes are fine.
+pub fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], plaintext: &[u8]) -> Vec<u8> {
+    let (mut cipher, mac) = cipher_and_mac(key, nonce);
+
+    let mut sealed = vec![0u8; TAG_LEN];
+    sealed.extend_from_slice(plaintext);
+    cipher.encrypt(&mut sealed[TAG_LEN..]);
+    let tag = mac.compute_unpadded(&sealed[TAG_LEN..]);
+    sealed[..TAG_LEN].copy_from_slice(&tag);
+    sealed
+}
+
+/// Verifies and decrypts a box produced by [`seal`] or libsodium.
+///
+/// The tag is checked in constant time before anything is decrypted.
+pub fn open(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], sealed: &[u8]) -> Result<Vec<u8>, SecretBoxError> {
+    if sealed.len() < TAG_LEN {
+        return Err(SecretBoxError::TooShort);
+    }
+    let (tag, ciphertext) = sealed.split_at(TAG_LEN);
+    let (mut cipher, mac) = cipher_and_mac(key, nonce);
+
+    let expected = mac.compute_unpadded(ciphertext);
+    if !bool::from(expected.as_slice().ct_eq(tag)) {
+        return Err(SecretBoxError::Authentication);
+    }
+
+    let mut plaintext = ciphertext.to_vec();
+    cipher.encrypt(&mut plaintext);
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_bytes;
+
+    // Key, nonce, message and box from libsodium's test/default/secretbox.c
+    const KEY: &str = "1b27556473e985d462cd51197a9a46c76009549eac6474f206c4ee0844f68389";
+    const NONCE: &str = "69696ee955b62b73cd62bda875fc73d68219e0036b7a0b37";
+    const MESSAGE: &str = "be075fc53c81f2d5cf141316ebeb0c7b5228c52a4c62cbd44b66849b64244ffc\
+                           e5ecbaaf33bd751a1ac728d45e6c61296cdc3c01233561f41db66cce314adb31\
+                           0e3be8250c46f06dceea3a7fa1348057e2f6556ad6b1318a024a838f21af1fde\
+                           048977eb48f59ffd4924ca1c60902e52f0a089bc76897040e082f93776384864\
+                           5e0705";
+    const SEALED: &str = "f3ffc7703f9400e52a7dfb4b3d3305d98e993b9f48681273c29650ba32fc76ce\
+                          48332ea7164d96a4476fb8c531a1186ac0dfc17c98dce87b4da7f011ec48c972\
+                          71d2c20f9b928fe2270d6fb863d51738b48eeee314a7cc8ab932164548e526ae\
+                          90224368517acfeabd6bb3732bc0e9da99832b61ca01b6de56244a9e88d5f9b3\
+                          7973f622a43d14a6599b1f654cb45a74e355a5";
+    // crypto_secretbox_easy of the empty message with the same key and nonce
+    const SEALED_EMPTY: &str = "2539121d8e234e652d651fa4c8cff880";
+
+    fn key_and_nonce() -> ([u8; KEY_LEN], [u8; NONCE_LEN]) {
+        (hex_bytes(KEY).try_into().unwrap(), hex_bytes(NONCE).try_into().unwrap())
+    }
+
+    #[test]
+    fn test_seal_matches_libsodium() {
+        let (key, nonce) = key_and_nonce();
+        assert_eq!(seal(&key, &nonce, &hex_bytes(MESSAGE)), hex_bytes(SEALED));
+        assert_eq!(seal(&key, &nonce, &[]), hex_bytes(SEALED_EMPTY));
+    }
+
+    #[test]
+    fn test_open_libsodium_boxes() {
+        let (key, nonce) = key_and_nonce();
+        assert_eq!(open(&key, &nonce, &hex_bytes(SEALED)).unwrap(), hex_bytes(MESSAGE));
+        assert_eq!(open(&key, &nonce, &hex_bytes(SEALED_EMPTY)).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_open_rejects_modified_boxes() {
+        let (key, nonce) = key_and_nonce();
+        let sealed = hex_bytes(SEALED);
+
+        for i in [0, TAG_LEN - 1, TAG_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 0x01;
+            assert_eq!(open(&key, &nonce, &tampered), Err(SecretBoxError::Authentication), "byte {}", i);
+        }
+
+        let mut other_nonce = nonce;
+        other_nonce[23] ^= 1;
+        assert_eq!(open(&key, &other_nonce, &sealed), Err(SecretBoxError::Authentication));
+        assert_eq!(open(&key, &nonce, &sealed[..sealed.len() - 1]), Err(SecretBoxError::Authentication));
+        assert_eq!(open(&key, &nonce, &sealed[..TAG_LEN - 1]), Err(SecretBoxError::TooShort));
+    }
+}
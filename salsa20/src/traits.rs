@@ -0,0 +1,135 @@
+//! RustCrypto `cipher` trait implementations, so [`Salsa20`] can be used by
+//! code written against `KeyIvInit`, `StreamCipher` and `StreamCipherSeek`.
+
+use crate::{Salsa20, BLOCK_SIZE};
+use cipher::consts::{U32, U8};
+use cipher::inout::InOutBuf;
+use cipher::{
+    IvSizeUser, KeyIvInit, KeySizeUser, OverflowError, SeekNum, StreamCipher, StreamCipherError, StreamCipherSeek,
+};
+
+impl<const R: usize> KeySizeUser for Salsa20<R> {
+    type KeySize = U32;
+}
+
+impl<const R: usize> IvSizeUser for Salsa20<R> {
+    type IvSize = U8;
+}
+
+impl<const R: usize> KeyIvInit for Salsa20<R> {
+    fn new(key: &cipher::Key<Self>, iv: &cipher::Iv<Self>) -> Salsa20<R> {
+        Salsa20::new(key.as_ref(), iv.as_ref())
+    }
+}
+
+impl<const R: usize> StreamCipher for Salsa20<R> {
+    fn try_apply_keystream_inout(&mut self, mut buf: InOutBuf<'_, '_, u8>) -> Result<(), StreamCipherError> {
+        if buf.len() as u128 > self.remaining() {
+            return Err(StreamCipherError);
+        }
+
+        // Input and output may be separate buffers, so XOR one block of
+        // keystream at a time from the input into the output
+        while !buf.is_empty() {
+            let len = buf.len().min(BLOCK_SIZE);
+            let (mut head, tail) = buf.split_at(len);
+            let mut keystream = [0u8; BLOCK_SIZE];
+            self.try_encrypt(&mut keystream[..len]).map_err(|_| StreamCipherError)?;
+            head.xor_in2out(&keystream[..len]);
+            buf = tail;
+        }
+        Ok(())
+    }
+}
+
+impl<const R: usize> StreamCipherSeek for Salsa20<R> {
+    fn try_current_pos<T: SeekNum>(&self) -> Result<T, OverflowError> {
+        // `SeekNum` expects the counter of the next block to generate, with
+        // `byte` counting into the block before it
+        let (block, byte) = self.block_and_byte();
+        let next = if byte == 0 { block } else { block + 1 };
+        let next = u64::try_from(next).map_err(|_| OverflowError)?;
+        T::from_block_byte(next, byte, BLOCK_SIZE as u8)
+    }
+
+    fn try_seek<T: SeekNum>(&mut self, pos: T) -> Result<(), StreamCipherError> {
+        let (block, byte): (u64, u8) = pos.into_block_byte(BLOCK_SIZE as u8).map_err(|_| StreamCipherError)?;
+        self.seek_block(block as u128, byte as usize);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, RngCore};
+
+    /// Encrypts with any `cipher`-trait implementation, the way generic callers do.
+    fn apply<C: KeyIvInit + StreamCipher + StreamCipherSeek>(key: &[u8; 32], nonce: &[u8; 8], pos: u64, data: &[u8]) -> Vec<u8> {
+        let mut cipher = C::new_from_slices(key, nonce).unwrap();
+        cipher.seek(pos);
+        let mut out = data.to_vec();
+        cipher.apply_keystream(&mut out);
+        assert_eq!(cipher.current_pos::<u64>(), pos + data.len() as u64);
+        out
+    }
+
+    fn differential<Ours, Reference>()
+    where
+        Ours: KeyIvInit + StreamCipher + StreamCipherSeek,
+        Reference: KeyIvInit + StreamCipher + StreamCipherSeek,
+    {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let key: [u8; 32] = rng.gen();
+            let nonce: [u8; 8] = rng.gen();
+            let mut data = vec![0u8; rng.gen_range(0..1000)];
+            rng.fill_bytes(&mut data);
+            let pos = rng.gen_range(0..10_000u64);
+
+            assert_eq!(
+                apply::<Ours>(&key, &nonce, pos, &data),
+                apply::<Reference>(&key, &nonce, pos, &data),
+                "len {} pos {}",
+                data.len(),
+                pos
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches_rustcrypto_salsa20() {
+        differential::<Salsa20<20>, salsa20::Salsa20>();
+        differential::<Salsa20<12>, salsa20::Salsa12>();
+        differential::<Salsa20<8>, salsa20::Salsa8>();
+    }
+
+    #[test]
+    fn test_separate_input_and_output_buffers() {
+        let mut rng = rand::thread_rng();
+        let key: [u8; 32] = rng.gen();
+        let nonce: [u8; 8] = rng.gen();
+        let mut input = vec![0u8; 300];
+        rng.fill_bytes(&mut input);
+
+        let mut output = vec![0u8; 300];
+        let mut cipher = <Salsa20 as KeyIvInit>::new(&key.into(), &nonce.into());
+        cipher.apply_keystream_b2b(&input, &mut output).unwrap();
+
+        let mut reference = input.clone();
+        salsa20::Salsa20::new(&key.into(), &nonce.into()).apply_keystream(&mut reference);
+        assert_eq!(output, reference);
+    }
+
+    #[test]
+    fn test_keystream_end_is_reported() {
+        let mut cipher = <Salsa20 as KeyIvInit>::new(&[1u8; 32].into(), &[2u8; 8].into());
+        let pos = u64::MAX as u128 * 64 - 4;
+        StreamCipherSeek::seek(&mut cipher, pos);
+        assert_eq!(StreamCipherSeek::current_pos::<u128>(&cipher), pos);
+        assert!(cipher.try_apply_keystream(&mut [0u8; 69]).is_err());
+        cipher.try_apply_keystream(&mut [0u8; 68]).unwrap();
+        // The end of the keystream lies past the last position `SeekNum` can express
+        assert!(cipher.try_current_pos::<u128>().is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! HSalsa20 subkey derivation and XSalsa20, Salsa20 with 192-bit nonces.
+//!
+//! XSalsa20 derives a subkey from the key and the first 16 nonce bytes with
+//! HSalsa20, then runs Salsa20 with that subkey and the last 8 nonce bytes.
+//! The nonce is long enough to be picked at random for every message.
+
+use crate::{initial_state, permute, Salsa20, Salsa20Error, ROUNDS};
+
+/// Derives a 32-byte subkey from `key` and a 16-byte `input`.
+///
+/// Runs the 20 Salsa20 rounds without the final addition and returns
+/// words 0, 5, 10, 15, 6, 7, 8 and 9 of the result.
+pub fn hsalsa20(key: &[u8; 32], input: &[u8; 16]) -> [u8; 32] {
+    let x = permute::<ROUNDS>(&initial_state(key, input));
+
+    let mut subkey = [0u8; 32];
+    for (bytes, i) in subkey.chunks_exact_mut(4).zip([0, 5, 10, 15, 6, 7, 8, 9]) {
+        bytes.copy_from_slice(&x[i].to_le_bytes());
+    }
+    subkey
+}
+
+/// The XSalsa20 stream cipher: Salsa20/20 keyed with an HSalsa20 subkey.
+///
+/// Safe to use with random 24-byte nonces. The keystream is 2^70 bytes long,
+/// as for [`Salsa20`].
+pub struct XSalsa20 {
+    inner: Salsa20,
+}
+
+impl XSalsa20 {
+    /// Starts the keystream for `key` and a 24-byte `nonce` at offset 0.
+    ///
+    /// The first 16 nonce bytes select the subkey and the last 8 become the
+    /// Salsa20 nonce.
+    pub fn new(key: &[u8; 32], nonce: &[u8; 24]) -> XSalsa20 {
+        let subkey = hsalsa20(key, nonce[..16].try_into().unwrap());
+        XSalsa20 { inner: Salsa20::new(&subkey, nonce[16..].try_into().unwrap()) }
+    }
+
+    /// XORs the keystream into `input`, continuing where the previous call stopped.
+    pub fn encrypt(&mut self, input: &mut [u8]) {
+        self.inner.encrypt(input);
+    }
+
+    /// Like [`XSalsa20::encrypt`], but returns an error on counter overflow.
+    pub fn try_encrypt(&mut self, input: &mut [u8]) -> Result<(), Salsa20Error> {
+        self.inner.try_encrypt(input)
+    }
+
+    /// Moves to byte `offset` of the keystream; see [`Salsa20::seek`].
+    pub fn seek(&mut self, offset: u128) -> Result<(), Salsa20Error> {
+        self.inner.seek(offset)
+    }
+
+    /// Byte offset of the next keystream byte to be used.
+    pub fn current_pos(&self) -> u128 {
+        self.inner.current_pos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_bytes;
+
+    // Key and nonce from the NaCl secretbox and stream tests
+    const FIRST_KEY: &str = "1b27556473e985d462cd51197a9a46c76009549eac6474f206c4ee0844f68389";
+    const NONCE: &str = "69696ee955b62b73cd62bda875fc73d68219e0036b7a0b37";
+
+    #[test]
+    fn test_hsalsa20_matches_nacl() {
+        let key: [u8; 32] = hex_bytes(FIRST_KEY).try_into().unwrap();
+        let nonce = hex_bytes(NONCE);
+        assert_eq!(
+            hsalsa20(&key, nonce[..16].try_into().unwrap())[..],
+            hex_bytes("dc908dda0b9344a953629b733820778880f3ceb421bb61b91cbd4c3e66256ce4")[..]
+        );
+    }
+
+    #[test]
+    fn test_xsalsa20_keystream_matches_reference() {
+        let key: [u8; 32] = hex_bytes(FIRST_KEY).try_into().unwrap();
+        let nonce: [u8; 24] = hex_bytes(NONCE).try_into().unwrap();
+        let mut stream = [0u8; 128];
+        XSalsa20::new(&key, &nonce).encrypt(&mut stream);
+
+        // Reference output from the RustCrypto salsa20 crate
+        assert_eq!(
+            stream[..],
+            hex_bytes(
+                "eea6a7251c1e72916d11c2cb214d3c252539121d8e234e652d651fa4c8cff880\
+                 309e645a74e9e0a60d8243acd9177ab51a1beb8d5a2f5d700c093c5e55855796\
+                 25337bd3ab619d615760d8c5b224a85b1d0efe0eb8a7ee163abb0376529fcc09\
+                 bab506c618e13ce777d82c3ae9d1a6f972d4160287cbfe60bf2130fc0a6ff604"
+            )[..]
+        );
+    }
+
+    #[test]
+    fn test_xsalsa20_round_trip_and_nonce_separation() {
+        let key = [5u8; 32];
+        let message = b"XSalsa20 takes 24-byte nonces".to_vec();
+
+        let mut ciphertext = message.clone();
+        XSalsa20::new(&key, &[1u8; 24]).encrypt(&mut ciphertext);
+        assert_ne!(ciphertext, message);
+
+        // Nonces differing only in the HSalsa20 half give different keystreams
+        let mut other_nonce = [1u8; 24];
+        other_nonce[0] = 2;
+        let mut other = message.clone();
+        XSalsa20::new(&key, &other_nonce).encrypt(&mut other);
+        assert_ne!(other, ciphertext);
+
+        XSalsa20::new(&key, &[1u8; 24]).encrypt(&mut ciphertext);
+        assert_eq!(ciphertext, message);
+    }
+
+    #[test]
+    fn test_xsalsa20_seek() {
+        let key: [u8; 32] = hex_bytes(FIRST_KEY).try_into().unwrap();
+        let nonce: [u8; 24] = hex_bytes(NONCE).try_into().unwrap();
+        let mut full = [0u8; 128];
+        XSalsa20::new(&key, &nonce).encrypt(&mut full);
+
+        let mut cipher = XSalsa20::new(&key, &nonce);
+        cipher.seek(70).unwrap();
+        let mut range = [0u8; 40];
+        cipher.encrypt(&mut range);
+        assert_eq!(range, full[70..110]);
+        assert_eq!(cipher.current_pos(), 110);
+    }
+}